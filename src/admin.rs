@@ -0,0 +1,415 @@
+//! Administrative endpoints, which are only available to users with `admin = true` in `./config/users.toml`.
+//! These report on the usage of the api from the generation history stored in the database, and allow the
+//! cache to be inspected and purged without access to the server.
+
+use std::collections::{BTreeMap, HashMap};
+
+use cache_manager::{Cache, PurgeReport};
+use chrono::{DateTime, Duration, Utc};
+use config::{models::Admin, Config};
+use macros::{failure, reject};
+use response::{Data, Problem, Response};
+use rocket::{
+    http::Status,
+    serde::json::{serde_json, Json},
+};
+use utils::phrase_package::PhrasePackage;
+
+use crate::database::Database;
+use crate::models::{
+    CachedPhrase, GenerationRequest, LatencySummary, PhraseCount, PinReport, UsageStats,
+};
+
+/// The window reported on if none is provided, in hours.
+const DEFAULT_WINDOW_HOURS: u32 = 24;
+
+/// The largest window which may be reported on, in hours.
+const MAX_WINDOW_HOURS: u32 = 24 * 366;
+
+/// The number of phrases reported in `top_phrases`.
+const TOP_PHRASE_COUNT: usize = 10;
+
+/// Returns the value at the provided percentile of a sorted list, using the nearest-rank method.
+fn percentile(sorted: &[u32], percentile: usize) -> u32 {
+    let rank = (percentile * sorted.len()).div_ceil(100);
+    sorted[rank.max(1) - 1]
+}
+
+/// Aggregates the generation requests made within a window into a summary of the usage of the api.
+pub fn usage_stats(
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    reqs: &[GenerationRequest],
+) -> UsageStats {
+    let mut phrases: HashMap<(&str, &str), usize> = HashMap::default();
+    let mut langs: BTreeMap<String, usize> = BTreeMap::default();
+    let mut formats: BTreeMap<String, usize> = BTreeMap::default();
+    for req in reqs {
+        *phrases.entry((&req.word, &req.lang)).or_default() += 1;
+        *langs.entry(req.lang.clone()).or_default() += 1;
+        *formats.entry(req.fmt.clone()).or_default() += 1;
+    }
+
+    let mut top_phrases: Vec<PhraseCount> = phrases
+        .into_iter()
+        .map(|((word, lang), count)| PhraseCount {
+            word: word.to_owned(),
+            lang: lang.to_owned(),
+            count,
+        })
+        .collect();
+    top_phrases.sort_by(|a, b| {
+        b.count
+            .cmp(&a.count)
+            .then_with(|| a.word.cmp(&b.word))
+            .then_with(|| a.lang.cmp(&b.lang))
+    });
+    top_phrases.truncate(TOP_PHRASE_COUNT);
+
+    let cached = reqs.iter().filter(|r| r.cached).count();
+    let cache_hit_ratio = match reqs.len() {
+        0 => 0.0,
+        n => cached as f64 / n as f64,
+    };
+
+    let mut latencies: Vec<u32> = reqs.iter().map(|r| r.latency_ms).collect();
+    latencies.sort_unstable();
+    let latency = match latencies.is_empty() {
+        true => None,
+        false => Some(LatencySummary {
+            p50_ms: percentile(&latencies, 50),
+            p95_ms: percentile(&latencies, 95),
+        }),
+    };
+
+    UsageStats {
+        from,
+        to,
+        total: reqs.len(),
+        cache_hit_ratio,
+        latency,
+        top_phrases,
+        langs,
+        formats,
+    }
+}
+
+/// Collects every generation request made within the last `hours` hours.
+async fn history(
+    hours: Option<u32>,
+    db: &Database,
+) -> Result<(DateTime<Utc>, DateTime<Utc>, Vec<GenerationRequest>), Response> {
+    let hours = hours.unwrap_or(DEFAULT_WINDOW_HOURS);
+    if hours == 0 || hours > MAX_WINDOW_HOURS {
+        reject!(
+            invalid_window,
+            "Requested window ({} hours) must be between 1 and {} hours!",
+            hours,
+            MAX_WINDOW_HOURS
+        );
+    }
+
+    let to = Utc::now();
+    let from = to - Duration::hours(hours as i64);
+    match db.requests_between(from, to).await {
+        Ok(reqs) => Ok((from, to, reqs)),
+        Err(e) => failure!(database_failure, "unable to load generation history {}", e),
+    }
+}
+
+/// Reports the most requested phrases, the number of requests per language and format, the cache hit ratio and
+/// the p50/p95 latency of requests over the last `hours` hours, defaulting to 24.
+/// Requires an administrator account to access.
+#[get("/admin/stats?<hours>")]
+pub async fn stats(
+    hours: Option<u32>,
+    _admin: Admin<'_>,
+    db: &Database,
+) -> Result<Response, Response> {
+    let (from, to, reqs) = history(hours, db).await?;
+
+    let data = match serde_json::to_string(&usage_stats(from, to, &reqs)) {
+        Ok(d) => d,
+        Err(e) => failure!(
+            serialization_failure,
+            "unable to serialize usage stats {}",
+            e
+        ),
+    };
+
+    Ok(Response::JsonOk(Data {
+        data,
+        status: Status::Ok,
+    }))
+}
+
+/// Exports every generation request made over the last `hours` hours as a csv file, defaulting to 24.
+/// Requires an administrator account to access.
+#[get("/admin/stats/export?<hours>")]
+pub async fn export(
+    hours: Option<u32>,
+    _admin: Admin<'_>,
+    db: &Database,
+) -> Result<Response, Response> {
+    let (_, _, reqs) = history(hours, db).await?;
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    let header = [
+        "id",
+        "usr_id",
+        "crt",
+        "word",
+        "lang",
+        "speed",
+        "fmt",
+        "cached",
+        "latency_ms",
+    ];
+    if let Err(e) = writer.write_record(header) {
+        failure!(csv_failure, "unable to write csv header {}", e);
+    }
+    for req in reqs {
+        let record = [
+            req.id.to_string(),
+            req.usr_id.to_string(),
+            req.crt.to_rfc3339(),
+            req.word,
+            req.lang,
+            req.speed.to_string(),
+            req.fmt,
+            req.cached.to_string(),
+            req.latency_ms.to_string(),
+        ];
+        if let Err(e) = writer.write_record(record) {
+            failure!(csv_failure, "unable to write csv record {}", e);
+        }
+    }
+
+    let data = match writer.into_inner() {
+        Ok(d) => String::from_utf8_lossy(&d).into_owned(),
+        Err(e) => failure!(csv_failure, "unable to write csv {}", e),
+    };
+
+    Ok(Response::CsvDownload((
+        Data {
+            data,
+            status: Status::Ok,
+        },
+        String::from("history.csv"),
+    )))
+}
+
+/// Serializes the outcome of a purge of the cache into a response.
+fn purge_response(report: PurgeReport) -> Response {
+    match serde_json::to_string(&report) {
+        Ok(data) => Response::JsonOk(Data {
+            data,
+            status: Status::Ok,
+        }),
+        Err(e) => Response::Problem(Problem::new(
+            Status::InternalServerError,
+            "serialization_failure",
+            format!("unable to serialize purge report {}", e),
+        )),
+    }
+}
+
+/// Every hash a phrase may be stored under in the cache, see `PhrasePackage::cached_stems()`.
+fn phrase_hashes(phrase: &CachedPhrase, cfg: &Config) -> Result<Vec<[u8; 32]>, hex::FromHexError> {
+    let phrase_package = PhrasePackage {
+        word: phrase.word.clone(),
+        lang: phrase.lang.clone(),
+        speed: 1.0,
+        fmt: None,
+        blacklist_policy: None,
+        bleeps: vec![],
+    };
+    phrase_package
+        .cached_stems(cfg)
+        .iter()
+        .map(|stem| {
+            let mut bytes: [u8; 32] = [0; 32];
+            hex::decode_to_slice(stem, &mut bytes).map(|_| bytes)
+        })
+        .collect()
+}
+
+/// Reports the current size of the cache against the maximum allowed size, the number of entries, and the most used hashes.
+/// Requires an administrator account to access.
+#[get("/admin/cache")]
+pub async fn cache_stats(_admin: Admin<'_>, cache: Cache) -> Result<Response, Response> {
+    let stats = match cache.stats().await {
+        Ok(s) => s,
+        Err(e) => failure!(cache_failure, "unable to collect cache stats {}", e),
+    };
+
+    let data = match serde_json::to_string(&stats) {
+        Ok(d) => d,
+        Err(e) => failure!(
+            serialization_failure,
+            "unable to serialize cache stats {}",
+            e
+        ),
+    };
+
+    Ok(Response::JsonOk(Data {
+        data,
+        status: Status::Ok,
+    }))
+}
+
+/// Removes the files named by a single hash from the cache, in any format.
+/// The hash is the file stem of the phrase in the cache, as generated by `PhrasePackage::filename_stem_basespeed()`
+/// or `PhrasePackage::filename_stem_properspeed()`. Files generated from the phrase at other speeds are named by
+/// other hashes, use `cache_purge_phrase` to remove every one of them. Requires an administrator account to access.
+#[delete("/admin/cache/<hash>")]
+pub async fn cache_purge(
+    hash: &str,
+    _admin: Admin<'_>,
+    cache: Cache,
+) -> Result<Response, Response> {
+    let mut bytes: [u8; 32] = [0; 32];
+    if hex::decode_to_slice(hash, &mut bytes).is_err() {
+        reject!(
+            invalid_hash,
+            "Provided hash ({}) is not a valid sha256 hash!",
+            hash
+        );
+    }
+
+    let report = match cache.purge(vec![bytes]).await {
+        Ok(r) => r,
+        Err(e) => failure!(cache_failure, "unable to purge cache {}", e),
+    };
+
+    if report.files_removed == 0 {
+        return Err(Response::Problem(
+            Problem::new(
+                Status::NotFound,
+                "hash_not_found",
+                format!("No files matching hash ({}) found in the cache!", hash),
+            )
+            .with_field("hash", hash),
+        ));
+    }
+
+    Ok(purge_response(report))
+}
+
+/// Removes a phrase from the cache entirely, at every speed and in every format, whether read in full or bleeped.
+/// Requires an administrator account to access.
+#[post("/admin/cache/purge", data = "<phrase>", format = "application/json")]
+pub async fn cache_purge_phrase(
+    phrase: Json<CachedPhrase>,
+    _admin: Admin<'_>,
+    cfg: &Config,
+    cache: Cache,
+) -> Result<Response, Response> {
+    let hashes = match phrase_hashes(&phrase, cfg) {
+        Ok(h) => h,
+        Err(e) => failure!(cache_failure, "unable to hash phrase {}", e),
+    };
+
+    let report = match cache.purge(hashes).await {
+        Ok(r) => r,
+        Err(e) => failure!(cache_failure, "unable to purge cache {}", e),
+    };
+
+    if report.files_removed == 0 {
+        return Err(Response::Problem(
+            Problem::new(
+                Status::NotFound,
+                "phrase_not_found",
+                format!("No files for phrase ({}) found in the cache!", phrase.word),
+            )
+            .with_field("word", &phrase.word),
+        ));
+    }
+
+    Ok(purge_response(report))
+}
+
+/// Pins a phrase, at every speed and in every format, so it is never trimmed from the cache or removed when the
+/// entire cache is purged. It may still be purged on its own.
+/// Requires an administrator account to access.
+#[put("/admin/cache/pin", data = "<phrase>", format = "application/json")]
+pub async fn cache_pin(
+    phrase: Json<CachedPhrase>,
+    _admin: Admin<'_>,
+    cfg: &Config,
+    cache: Cache,
+) -> Result<Response, Response> {
+    let hashes = match phrase_hashes(&phrase, cfg) {
+        Ok(h) => h,
+        Err(e) => failure!(cache_failure, "unable to hash phrase {}", e),
+    };
+
+    let changed = match cache.pin(hashes).await {
+        Ok(c) => c,
+        Err(e) => failure!(cache_failure, "unable to pin phrase {}", e),
+    };
+
+    Ok(pin_response(changed))
+}
+
+/// Unpins a phrase, so it may be trimmed from the cache again.
+/// Requires an administrator account to access.
+#[delete("/admin/cache/pin", data = "<phrase>", format = "application/json")]
+pub async fn cache_unpin(
+    phrase: Json<CachedPhrase>,
+    _admin: Admin<'_>,
+    cfg: &Config,
+    cache: Cache,
+) -> Result<Response, Response> {
+    let hashes = match phrase_hashes(&phrase, cfg) {
+        Ok(h) => h,
+        Err(e) => failure!(cache_failure, "unable to hash phrase {}", e),
+    };
+
+    let changed = match cache.unpin(hashes).await {
+        Ok(c) => c,
+        Err(e) => failure!(cache_failure, "unable to unpin phrase {}", e),
+    };
+
+    Ok(pin_response(changed))
+}
+
+/// Serializes the number of hashes changed by pinning or unpinning a phrase into a response.
+fn pin_response(changed: usize) -> Response {
+    match serde_json::to_string(&PinReport {
+        hashes_changed: changed,
+    }) {
+        Ok(data) => Response::JsonOk(Data {
+            data,
+            status: Status::Ok,
+        }),
+        Err(e) => Response::Problem(Problem::new(
+            Status::InternalServerError,
+            "serialization_failure",
+            format!("unable to serialize pin report {}", e),
+        )),
+    }
+}
+
+/// Removes every phrase from the cache, other than those which are pinned. Requires an administrator account to access.
+#[delete("/admin/cache")]
+pub async fn cache_purge_all(_admin: Admin<'_>, cache: Cache) -> Result<Response, Response> {
+    let report = match cache.purge_all().await {
+        Ok(r) => r,
+        Err(e) => failure!(cache_failure, "unable to purge cache {}", e),
+    };
+
+    Ok(purge_response(report))
+}
+
+/// Trims the cache immediately if it is larger than `MAX_CACHE_SIZE_MB`, rather than waiting for the
+/// next enforcement. Requires an administrator account to access.
+#[post("/admin/cache/enforce")]
+pub async fn cache_enforce(_admin: Admin<'_>, cache: Cache) -> Result<Response, Response> {
+    let report = match cache.enforce().await {
+        Ok(r) => r,
+        Err(e) => failure!(cache_failure, "unable to enforce cache size {}", e),
+    };
+
+    Ok(purge_response(report))
+}
@@ -0,0 +1,229 @@
+//! Persistent storage for the api, backed by an embedded SQLite database.
+//! Every phrase generated for a user is recorded, providing an audit trail of what was synthesized, when, and by whom.
+
+use std::{
+    convert::Infallible,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use config::{models::User, Config};
+use rocket::{
+    fairing::AdHoc,
+    request::{self, FromRequest},
+    tokio::task::spawn_blocking,
+    Request,
+};
+use rusqlite::{params, Connection};
+use utils::phrase_package::PhrasePackage;
+
+use crate::models::{GenerationRequest, NewGenerationRequest};
+
+/// The migrations which are applied to the database on startup, in order.
+/// The index of the last applied migration is tracked through the `user_version` of the database,
+/// so existing migrations must never be modified, only appended to.
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE reqs (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        usr_id INTEGER NOT NULL,
+        crt TEXT NOT NULL,
+        word TEXT NOT NULL,
+        lang TEXT NOT NULL,
+        speed REAL NOT NULL,
+        fmt TEXT NOT NULL
+    );
+    CREATE INDEX reqs_crt ON reqs (crt);",
+    "ALTER TABLE reqs ADD COLUMN cached INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE reqs ADD COLUMN latency_ms INTEGER NOT NULL DEFAULT 0;",
+];
+
+#[derive(Debug)]
+pub enum DatabaseError {
+    Sqlite(rusqlite::Error),
+    Join(rocket::tokio::task::JoinError),
+}
+
+impl std::fmt::Display for DatabaseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            DatabaseError::Sqlite(ref e) => write!(f, "database error {}", e),
+            DatabaseError::Join(_) => write!(f, "database task failed to complete"),
+        }
+    }
+}
+
+impl std::error::Error for DatabaseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            DatabaseError::Sqlite(ref e) => Some(e),
+            DatabaseError::Join(ref e) => Some(e),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for DatabaseError {
+    fn from(e: rusqlite::Error) -> Self {
+        DatabaseError::Sqlite(e)
+    }
+}
+
+impl From<rocket::tokio::task::JoinError> for DatabaseError {
+    fn from(e: rocket::tokio::task::JoinError) -> Self {
+        DatabaseError::Join(e)
+    }
+}
+
+/// A handle to the database, which may be cheaply cloned.
+#[derive(Debug, Clone)]
+pub struct Database {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl Database {
+    /// Open the database at the provided path, creating it if it does not exist, and apply any outstanding migrations.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, DatabaseError> {
+        let mut conn = Connection::open(path)?;
+        Self::migrate(&mut conn)?;
+        Ok(Database {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Apply any migrations which have not yet been applied to the database.
+    fn migrate(conn: &mut Connection) -> Result<(), DatabaseError> {
+        let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            let tx = conn.transaction()?;
+            tx.execute_batch(migration)?;
+            tx.pragma_update(None, "user_version", i + 1)?;
+            tx.commit()?;
+        }
+
+        Ok(())
+    }
+
+    /// Run a closure against the database connection on a blocking thread, so as not to stall the async runtime.
+    async fn run<F, T>(&self, f: F) -> Result<T, DatabaseError>
+    where
+        F: FnOnce(&mut Connection) -> Result<T, rusqlite::Error> + Send + 'static,
+        T: Send + 'static,
+    {
+        let conn = self.conn.clone();
+        let res = spawn_blocking(move || {
+            let mut conn = conn.lock().expect("database lock poisoned");
+            f(&mut conn)
+        })
+        .await??;
+        Ok(res)
+    }
+
+    /// Store a new generation request.
+    pub async fn insert_request(&self, req: NewGenerationRequest) -> Result<(), DatabaseError> {
+        self.run(move |conn| {
+            conn.execute(
+                "INSERT INTO reqs (usr_id, crt, word, lang, speed, fmt, cached, latency_ms)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    req.usr_id,
+                    Utc::now(),
+                    req.word,
+                    req.lang,
+                    req.speed,
+                    req.fmt,
+                    req.cached,
+                    req.latency_ms
+                ],
+            )
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Record that a phrase was generated for a user, whether it was served from the cache, and how long it took.
+    /// Failures are logged rather than returned, as a failure to record a request should not prevent the user
+    /// from recieving their phrase.
+    pub async fn record(
+        &self,
+        phrase_package: &PhrasePackage,
+        user: &User,
+        cached: bool,
+        latency: Duration,
+    ) {
+        let req = NewGenerationRequest {
+            usr_id: user.id,
+            word: phrase_package.word.clone(),
+            lang: phrase_package.lang.clone(),
+            speed: phrase_package.speed,
+            fmt: phrase_package.format().to_owned(),
+            cached,
+            latency_ms: latency.as_millis().min(u32::MAX as u128) as u32,
+        };
+        if let Err(e) = self.insert_request(req).await {
+            error!("failed to record generation request {}", e);
+        }
+    }
+
+    /// Collect all generation requests made between the provided times, ordered from oldest to newest.
+    pub async fn requests_between(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<GenerationRequest>, DatabaseError> {
+        self.run(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT id, usr_id, crt, word, lang, speed, fmt, cached, latency_ms FROM reqs
+                WHERE crt >= ?1 AND crt < ?2 ORDER BY crt, id",
+            )?;
+            let rows = stmt.query_map(params![from, to], |row| {
+                Ok(GenerationRequest {
+                    id: row.get(0)?,
+                    usr_id: row.get(1)?,
+                    crt: row.get(2)?,
+                    word: row.get(3)?,
+                    lang: row.get(4)?,
+                    speed: row.get(5)?,
+                    fmt: row.get(6)?,
+                    cached: row.get(7)?,
+                    latency_ms: row.get(8)?,
+                })
+            })?;
+            rows.collect()
+        })
+        .await
+    }
+
+    pub fn fairing() -> AdHoc {
+        AdHoc::on_ignite("Database", |rocket| {
+            Box::pin(async move {
+                let cfg = rocket
+                    .state::<Config>()
+                    .expect("Configuration Fairing Not Attached!");
+
+                let db = Database::open(cfg.DATABASE_PATH()).unwrap_or_else(|e| {
+                    panic!(
+                        "Unable to open database {} due to {}",
+                        cfg.DATABASE_PATH(),
+                        e
+                    )
+                });
+
+                rocket.manage(db)
+            })
+        })
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for &'r Database {
+    type Error = Infallible;
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Infallible> {
+        let state = req
+            .rocket()
+            .state::<Database>()
+            .expect("database fairing attached");
+        request::Outcome::Success(state)
+    }
+}
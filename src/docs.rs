@@ -0,0 +1,159 @@
+//! Serves the OpenAPI specification of the api from `openapi.oas.yml`, alongside a page to browse it at `/docs`.
+//! The languages, formats and speeds accepted by the api are filled in from the configuration when the api starts,
+//! so the documentation always matches the running server.
+
+use std::convert::Infallible;
+
+use config::Config;
+use converter::Converter;
+use response::{Data, Response};
+use rocket::{
+    fairing::AdHoc,
+    http::Status,
+    request::{self, FromRequest},
+    serde::json::{serde_json, Value},
+    Request,
+};
+use utils::escape_html;
+
+/// The specification, as written. Parts of this are replaced by `render_spec()`.
+const SPEC: &str = include_str!("../openapi.oas.yml");
+
+/// The page served at `/docs`, which renders the specification with Redoc.
+/// Redoc is pinned to a single release, so the page only changes when it is updated here.
+const DOCS_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+  <head>
+    <title>{name} TTS API</title>
+    <meta charset="utf-8"/>
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+  </head>
+  <body>
+    <redoc spec-url="/openapi.json"></redoc>
+    <script src="https://cdn.jsdelivr.net/npm/redoc@2.1.3/bundles/redoc.standalone.js" crossorigin="anonymous"></script>
+  </body>
+</html>
+"#;
+
+/// Merge the provided keys into an object within the specification, if it exists.
+fn merge(spec: &mut Value, pointer: &str, values: &Value) {
+    if let (Some(Value::Object(target)), Value::Object(values)) =
+        (spec.pointer_mut(pointer), values)
+    {
+        target.extend(values.clone());
+    }
+}
+
+/// Generate the specification of this api, with the enabled languages, the formats which may be produced and
+/// the range of speeds filled in from the configuration.
+pub fn render_spec(
+    cfg: &Config,
+    converter: &Converter,
+) -> Result<Value, Box<dyn std::error::Error>> {
+    let spec: serde_yaml::Value = serde_yaml::from_str(SPEC)?;
+    let mut spec = serde_json::to_value(spec)?;
+
+    let mut langs: Vec<&str> = cfg
+        .SUPPORTED_LANGS()
+        .values()
+        .filter(|l| l.enabled)
+        .map(|l| l.iso_691_code.as_str())
+        .collect();
+    langs.sort_unstable();
+
+    let mut formats: Vec<&str> = cfg
+        .ALLOWED_FORMATS()
+        .iter()
+        .filter(|fmt| converter.is_supported(fmt))
+        .map(|fmt| fmt.as_str())
+        .collect();
+    formats.sort_unstable();
+
+    let fields = [
+        ("lang", serde_json::json!({ "enum": langs })),
+        ("fmt", serde_json::json!({ "enum": formats })),
+        (
+            "speed",
+            serde_json::json!({ "minimum": cfg.SPEED_MIN_VAL(), "maximum": cfg.SPEED_MAX_VAL() }),
+        ),
+    ];
+    for (field, values) in fields.iter() {
+        merge(
+            &mut spec,
+            &format!("/components/schemas/PhrasePackage/properties/{}", field),
+            values,
+        );
+
+        //The url based convert endpoint accepts the same fields as query parameters
+        let params = spec
+            .pointer("/paths/~1api~1convert/get/parameters")
+            .and_then(|p| p.as_array())
+            .map(|p| p.len())
+            .unwrap_or_default();
+        for i in 0..params {
+            let param = format!("/paths/~1api~1convert/get/parameters/{}", i);
+            if spec.pointer(&format!("{}/name", param)) == Some(&Value::from(*field)) {
+                merge(&mut spec, &format!("{}/schema", param), values);
+            }
+        }
+    }
+
+    spec["info"]["version"] = Value::from(env!("CARGO_PKG_VERSION"));
+    Ok(spec)
+}
+
+/// The rendered specification of this api.
+#[derive(Debug, Clone)]
+pub struct Docs {
+    spec: String,
+}
+
+impl Docs {
+    /// Generate an adhoc fairing which can be bound to a launching rocket.
+    /// Must be attached after the config and converter fairings.
+    pub fn fairing() -> AdHoc {
+        AdHoc::on_ignite("Docs", |rocket| {
+            Box::pin(async move {
+                let cfg = rocket
+                    .state::<Config>()
+                    .expect("Configuration Fairing Not Attached!");
+                let converter = rocket
+                    .state::<Converter>()
+                    .expect("converter fairing attached");
+
+                let spec = render_spec(cfg, converter)
+                    .and_then(|s| Ok(serde_json::to_string(&s)?))
+                    .unwrap_or_else(|e| panic!("Unable to render openapi.oas.yml due to {}", e));
+
+                rocket.manage(Docs { spec })
+            })
+        })
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for &'r Docs {
+    type Error = Infallible;
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Infallible> {
+        let state = req.rocket().state::<Docs>().expect("docs fairing attached");
+        request::Outcome::Success(state)
+    }
+}
+
+/// Returns the OpenAPI specification of this api, as json.
+#[get("/openapi.json")]
+pub async fn spec(docs: &Docs) -> Response {
+    Response::JsonOk(Data {
+        data: docs.spec.clone(),
+        status: Status::Ok,
+    })
+}
+
+/// Returns a page which renders the OpenAPI specification of this api.
+#[get("/docs")]
+pub async fn page(cfg: &Config) -> Response {
+    Response::HtmlOk(Data {
+        data: DOCS_PAGE.replace("{name}", &escape_html(cfg.API_NAME())),
+        status: Status::Ok,
+    })
+}
@@ -0,0 +1,98 @@
+//! Liveness and readiness probes, intended for use by an orchestrator such as Kubernetes.
+//! Readiness verifies the external dependencies of the api, so a misconfigured deployment is detected
+//! before it recieves traffic rather than on the first request.
+
+use std::{collections::BTreeMap, path::Path};
+
+use config::{missing_voices, Config};
+use converter::Ffmpeg;
+use festvox::Flite;
+use macros::failure;
+use response::{Data, Response};
+use rocket::{http::Status, serde::json::serde_json, tokio};
+use utils::generate_random_alphanumeric;
+
+use crate::models::{HealthCheck, Readiness};
+
+impl From<Result<(), String>> for HealthCheck {
+    fn from(res: Result<(), String>) -> Self {
+        HealthCheck {
+            ok: res.is_ok(),
+            error: res.err(),
+        }
+    }
+}
+
+/// Check that every enabled language has a voice file available, or uses a voice built in to flite.
+fn check_voices(cfg: &Config) -> Result<(), String> {
+    let missing: Vec<String> = missing_voices(cfg.SUPPORTED_LANGS())
+        .into_iter()
+        .map(|lang| format!("{} ({})", lang.festival_code, lang.iso_691_code))
+        .collect();
+
+    match missing.is_empty() {
+        true => Ok(()),
+        false => Err(format!("missing voice files {}", missing.join(", "))),
+    }
+}
+
+/// Check that files can be written to, and removed from, the cache.
+async fn check_cache(cfg: &Config) -> Result<(), String> {
+    let path = Path::new(cfg.CACHE_PATH())
+        .join(format!(".health-check-{}", generate_random_alphanumeric(8)));
+    tokio::fs::write(&path, b"health check")
+        .await
+        .map_err(|e| format!("unable to write to {} due to {}", cfg.CACHE_PATH(), e))?;
+    tokio::fs::remove_file(&path)
+        .await
+        .map_err(|e| format!("unable to remove from {} due to {}", cfg.CACHE_PATH(), e))
+}
+
+/// Reports that the api is running, this makes no attempt to check that the api can serve requests.
+#[get("/live")]
+pub fn live() -> Response {
+    Response::JsonOk(Data {
+        data: String::from("{\"status\":\"ok\"}"),
+        status: Status::Ok,
+    })
+}
+
+/// Reports whether the api is able to serve requests, checking that flite and ffmpeg run, that every enabled
+/// language has a voice file or uses a voice built in to flite, and that the cache is writable. Returns a 503 if any
+/// check fails.
+#[get("/ready")]
+pub async fn ready(cfg: &Config, generator: &Flite) -> Result<Response, Response> {
+    let mut checks = BTreeMap::new();
+
+    checks.insert(
+        "flite",
+        generator
+            .check()
+            .await
+            .map_err(|e| format!("{:?}", e))
+            .into(),
+    );
+
+    let ffmpeg = match tokio::task::spawn_blocking(Ffmpeg::new).await {
+        Ok(res) => res.map(|_| ()),
+        Err(e) => Err(e.to_string()),
+    };
+    checks.insert("ffmpeg", ffmpeg.into());
+
+    checks.insert("voices", check_voices(cfg).into());
+    checks.insert("cache", check_cache(cfg).await.into());
+
+    let ready = checks.values().all(|c: &HealthCheck| c.ok);
+    let data = match serde_json::to_string(&Readiness { ready, checks }) {
+        Ok(d) => d,
+        Err(e) => failure!(serialization_failure, "unable to serialize readiness {}", e),
+    };
+
+    Ok(Response::JsonOk(Data {
+        data,
+        status: match ready {
+            true => Status::Ok,
+            false => Status::ServiceUnavailable,
+        },
+    }))
+}
@@ -0,0 +1,346 @@
+//! Asynchronous generation jobs, allowing a phrase to be generated in the background while the client polls for the result.
+//! This avoids holding a connection open for the full flite/ffmpeg pipeline, which may be slow for long phrases or formats.
+
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use cache_manager::Cache;
+use config::{models::User, Config};
+use converter::Converter;
+use festvox::Flite;
+use macros::failure;
+use rate_limiter::{ClientId, RateLimiter};
+use response::{Data, Disposition, FileRequest, Problem, Response};
+use rocket::{
+    fairing::AdHoc,
+    http::{Accept, Status},
+    request::{self, FromRequest},
+    serde::json::{serde_json, Json},
+    tokio::sync::{RwLock, Semaphore},
+    Request,
+};
+use utils::{generate_random_alphanumeric, phrase_package::PhrasePackage};
+
+use crate::database::Database;
+use crate::metrics::{Metrics, RequestLabels};
+use crate::models::{JobCreated, JobReport};
+
+/// The number of jobs which may be generated at once, any further jobs will remain queued until a worker is free.
+const WORKER_COUNT: usize = 4;
+
+/// How long a finished job is kept for before it is forgotten.
+const JOB_RETENTION: Duration = Duration::from_secs(60 * 60);
+
+/// The current state of a job.
+#[derive(Debug, Clone)]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Done(PathBuf),
+    Failed(Problem),
+}
+
+impl JobStatus {
+    /// A short name for this status, as reported to the user.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            JobStatus::Queued => "queued",
+            JobStatus::Running => "running",
+            JobStatus::Done(_) => "done",
+            JobStatus::Failed(_) => "failed",
+        }
+    }
+}
+
+/// Forget any finished jobs which have been kept for longer than `JOB_RETENTION`.
+fn prune(jobs: &mut HashMap<String, Job>) {
+    jobs.retain(|_, job| match job.status {
+        JobStatus::Done(_) | JobStatus::Failed(_) => job.updated.elapsed() < JOB_RETENTION,
+        _ => true,
+    });
+}
+
+#[derive(Debug)]
+struct Job {
+    usr_id: i32,
+    status: JobStatus,
+    fmt: String,
+    updated: Instant,
+}
+
+/// Everything required to generate a phrase outside of a request.
+#[derive(Debug)]
+struct JobContext {
+    generator: Flite,
+    converter: Converter,
+    cfg: Config,
+    cache: Cache,
+    db: Database,
+    metrics: Metrics,
+}
+
+/// Tracks and executes generation jobs in the background.
+#[derive(Debug, Clone)]
+pub struct Jobs {
+    jobs: Arc<RwLock<HashMap<String, Job>>>,
+    context: Arc<JobContext>,
+    workers: Arc<Semaphore>,
+}
+
+impl Jobs {
+    /// Generate an adhoc fairing which can be bound to a launching rocket.
+    /// Must be attached after the config, metrics, database, flite, converter and cache fairings.
+    pub fn fairing() -> AdHoc {
+        AdHoc::on_ignite("Job Runner", |rocket| {
+            Box::pin(async move {
+                let context = JobContext {
+                    generator: rocket
+                        .state::<Flite>()
+                        .expect("flite fairing attached")
+                        .clone(),
+                    converter: rocket
+                        .state::<Converter>()
+                        .expect("converter fairing attached")
+                        .clone(),
+                    cfg: rocket
+                        .state::<Config>()
+                        .expect("config fairing attached")
+                        .clone(),
+                    cache: rocket
+                        .state::<Cache>()
+                        .expect("cache manager attached")
+                        .clone(),
+                    db: rocket
+                        .state::<Database>()
+                        .expect("database fairing attached")
+                        .clone(),
+                    metrics: rocket
+                        .state::<Metrics>()
+                        .expect("metrics fairing attached")
+                        .clone(),
+                };
+
+                rocket.manage(Jobs {
+                    jobs: Arc::new(RwLock::new(HashMap::default())),
+                    context: Arc::new(context),
+                    workers: Arc::new(Semaphore::new(WORKER_COUNT)),
+                })
+            })
+        })
+    }
+
+    /// Queue a validated phrase package to be generated on behalf of a user, returning the id of the new job.
+    pub async fn submit(&self, phrase_package: PhrasePackage, user: &User) -> String {
+        let id = generate_random_alphanumeric(32);
+
+        {
+            let mut jobs = self.jobs.write().await;
+            prune(&mut jobs);
+            jobs.insert(
+                id.clone(),
+                Job {
+                    usr_id: user.id,
+                    status: JobStatus::Queued,
+                    fmt: phrase_package.format().to_owned(),
+                    updated: Instant::now(),
+                },
+            );
+        }
+
+        let this = self.clone();
+        let job_id = id.clone();
+        let user = user.clone();
+        rocket::tokio::spawn(async move {
+            let _permit = this.workers.acquire().await;
+            this.set_status(&job_id, JobStatus::Running).await;
+
+            let ctx = &this.context;
+            let cached = phrase_package.converted_path(&ctx.cfg).exists();
+            let start = Instant::now();
+            let status = match crate::generate_and_convert(
+                &phrase_package,
+                &ctx.generator,
+                &ctx.converter,
+                &ctx.cfg,
+                &ctx.cache,
+                &ctx.metrics,
+            )
+            .await
+            {
+                Ok(path) => {
+                    ctx.db
+                        .record(&phrase_package, &user, cached, start.elapsed())
+                        .await;
+                    JobStatus::Done(path)
+                }
+                Err(e) => JobStatus::Failed(crate::into_problem(e)),
+            };
+
+            this.set_status(&job_id, status).await;
+        });
+
+        id
+    }
+
+    /// Returns the current status of a job, alongside the format it is being generated in.
+    /// Jobs may only be viewed by the user who created them.
+    pub async fn status(&self, id: &str, user: &User) -> Option<(JobStatus, String)> {
+        let mut jobs = self.jobs.write().await;
+        prune(&mut jobs);
+        jobs.get(id)
+            .filter(|job| job.usr_id == user.id)
+            .map(|job| (job.status.clone(), job.fmt.clone()))
+    }
+
+    async fn set_status(&self, id: &str, status: JobStatus) {
+        if let Some(job) = self.jobs.write().await.get_mut(id) {
+            job.status = status;
+            job.updated = Instant::now();
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for &'r Jobs {
+    type Error = Infallible;
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Infallible> {
+        let state = req
+            .rocket()
+            .state::<Jobs>()
+            .expect("job runner fairing attached");
+        request::Outcome::Success(state)
+    }
+}
+
+/// Expects a phrase package, which is validated and then queued to be generated in the background.
+/// Returns the id of the job immediately, which may be polled to check on the progress of the generation.
+/// If no format is provided, one is chosen from the `Accept` header of the request. Requires an authenticated user account to access.
+#[allow(clippy::too_many_arguments)]
+#[post("/jobs", data = "<phrase_package>", format = "application/json")]
+pub async fn create(
+    mut phrase_package: Json<PhrasePackage>,
+    accept: Option<&Accept>,
+    user: &User,
+    client: ClientId,
+    limiter: &RateLimiter,
+    converter: &Converter,
+    cfg: &Config,
+    jobs: &Jobs,
+    labels: RequestLabels<'_>,
+) -> Result<Response, Response> {
+    // Choose a format, then validate PhrasePackage
+    crate::negotiate_format(&mut phrase_package, accept, converter, cfg)
+        .map_err(Response::Problem)?;
+    phrase_package
+        .validated(cfg)
+        .map_err(|e| Response::Problem(crate::invalid_phrase(e)))?;
+    labels.set(&phrase_package);
+
+    // Enforce rate limits, only phrases which are not yet cached are charged against the generation budget
+    let cached = phrase_package.converted_path(cfg).exists() as u32;
+    limiter
+        .acquire(&client, cached, 1 - cached)
+        .map_err(crate::rate_limited)?;
+
+    let id = jobs.submit(phrase_package.into_inner(), user).await;
+
+    let data = match serde_json::to_string(&JobCreated { id: &id }) {
+        Ok(d) => d,
+        Err(e) => failure!(serialization_failure, "unable to serialize job {}", e),
+    };
+
+    Ok(Response::JsonOk(Data {
+        data,
+        status: Status::Accepted,
+    }))
+}
+
+/// Reports the status of a job, which may be one of `queued`, `running`, `done` or `failed`.
+/// Only the user who created the job may view it.
+#[get("/jobs/<id>")]
+pub async fn status(id: &str, user: &User, jobs: &Jobs) -> Result<Response, Response> {
+    let (status, _) = match jobs.status(id, user).await {
+        Some(s) => s,
+        None => {
+            return Err(Response::Problem(Problem::new(
+                Status::NotFound,
+                "job_not_found",
+                format!("Job ({}) not found!", id),
+            )))
+        }
+    };
+
+    let (code, error) = match status {
+        JobStatus::Failed(ref p) => (Some(p.code.as_str()), Some(p.detail.as_str())),
+        _ => (None, None),
+    };
+
+    let data = match serde_json::to_string(&JobReport {
+        id,
+        status: status.name(),
+        code,
+        error,
+    }) {
+        Ok(d) => d,
+        Err(e) => failure!(serialization_failure, "unable to serialize job {}", e),
+    };
+
+    Ok(Response::JsonOk(Data {
+        data,
+        status: Status::Ok,
+    }))
+}
+
+/// Downloads the sound file generated by a job, this is only available once the job is `done`.
+/// Only the user who created the job may download it.
+#[get("/jobs/<id>/audio?<disposition>")]
+pub async fn audio(
+    id: &str,
+    disposition: Option<Disposition>,
+    file_request: FileRequest<'_>,
+    user: &User,
+    jobs: &Jobs,
+    converter: &Converter,
+    cfg: &Config,
+) -> Result<Response, Response> {
+    let (status, fmt) = match jobs.status(id, user).await {
+        Some(s) => s,
+        None => {
+            return Err(Response::Problem(Problem::new(
+                Status::NotFound,
+                "job_not_found",
+                format!("Job ({}) not found!", id),
+            )))
+        }
+    };
+
+    let path = match status {
+        JobStatus::Done(path) => path,
+        s => {
+            return Err(Response::Problem(Problem::new(
+                Status::Conflict,
+                "job_not_done",
+                format!("Job ({}) is {}, audio is not available!", id, s.name()),
+            )))
+        }
+    };
+
+    //The file may have since been trimmed from the cache, or purged by an administrator
+    if !path.exists() {
+        return Err(Response::Problem(Problem::new(
+            Status::Gone,
+            "job_audio_expired",
+            format!(
+                "Audio of job ({}) has been removed from the cache, create a new job to generate it again!",
+                id
+            ),
+        )));
+    }
+
+    crate::audio_file(path, &fmt, converter, cfg, disposition, file_request).await
+}
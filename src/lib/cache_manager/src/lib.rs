@@ -6,12 +6,13 @@ use rocket::{
     futures::future::join_all,
     info,
     request::FromRequest,
+    serde::Serialize,
     tokio::{
         self,
         runtime::Runtime,
         sync::{
             mpsc::{self},
-            RwLock,
+            oneshot, RwLock,
         },
     },
     warn,
@@ -29,6 +30,42 @@ use std::{
     thread,
 };
 
+/// The number of hashes reported as most used in `CacheStats`.
+const MOST_USED_COUNT: usize = 10;
+
+/// The file in the cache folder which records the pinned hashes, one per line.
+const PINS_FILE: &str = ".pinned";
+
+/// The number of times a single item in the cache has been used.
+#[derive(Debug, Clone, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct CacheUsage {
+    pub hash: String,
+    pub uses: u32,
+}
+
+/// A snapshot of the current state of the cache.
+#[derive(Debug, Clone, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct CacheStats {
+    pub current_size_bytes: u64,
+    pub max_allowed_size_bytes: u64,
+    pub entries: usize,
+    /// The number of hashes which are pinned, and so never evicted.
+    pub pinned: usize,
+    /// The total number of files removed to keep the cache under the maximum allowed size.
+    pub evicted_files_total: u64,
+    pub most_used: Vec<CacheUsage>,
+}
+
+/// The files removed from the cache by a purge or an enforcement of the cache size.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct PurgeReport {
+    pub files_removed: u64,
+    pub bytes_removed: u64,
+}
+
 /// Handles the size and implementation of the cache folder for any application
 /// automatically removing files as needed.
 #[derive(Debug)]
@@ -37,8 +74,11 @@ pub struct CacheManager {
     cache_path: PathBuf,
     max_allowed_size_bytes: u64,
     current_size_bytes: u64,
+    evicted_files_total: u64,
     rx: mpsc::UnboundedReceiver<CacheAction>,
     restricted_files: HashSet<String>,
+    /// Hashes which are never removed by an enforcement of the cache size, or a purge of the entire cache.
+    pinned: HashSet<[u8; 32]>,
 }
 
 impl CacheManager {
@@ -52,11 +92,32 @@ impl CacheManager {
             cache_path,
             max_allowed_size_bytes: max_allowed_size_mb * 1_000_000,
             current_size_bytes: 0,
+            evicted_files_total: 0,
             rx,
             cache: PriorityQueue::new(),
-            restricted_files: vec![String::from(".gitkeep")].into_iter().collect(),
+            restricted_files: vec![String::from(".gitkeep"), String::from(PINS_FILE)]
+                .into_iter()
+                .collect(),
+            pinned: HashSet::default(),
         };
 
+        //Load the pinned hashes, which may not exist if nothing has been pinned
+        match std::fs::read_to_string(res.cache_path.join(PINS_FILE)) {
+            Ok(pins) => {
+                for line in pins.lines().filter(|l| !l.trim().is_empty()) {
+                    let mut bytes: [u8; 32] = [0; 32];
+                    match hex::decode_to_slice(line.trim().as_bytes(), &mut bytes) {
+                        Ok(_) => {
+                            res.pinned.insert(bytes);
+                        }
+                        Err(_) => error!("found unexpected pin in cache {}", line),
+                    }
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => panic!("unable to read pinned files in cache due to {}", e),
+        }
+
         //Get current total size of files
         let paths = match std::fs::read_dir(&res.cache_path) {
             Ok(f) => f,
@@ -107,8 +168,10 @@ impl CacheManager {
         Ok((res, tx))
     }
 
-    /// If the cache is greater than the maximum allowed size, trims files in the cache
-    pub async fn enforce_cache_size(&mut self) {
+    /// If the cache is greater than the maximum allowed size, trims files in the cache.
+    /// Returns the files which were removed, if any.
+    pub async fn enforce_cache_size(&mut self) -> PurgeReport {
+        let mut report = PurgeReport::default();
         if self.current_size_bytes > self.max_allowed_size_bytes {
             info!("Enforcing cache size");
             //If greater than allowed, trim files
@@ -121,6 +184,7 @@ impl CacheManager {
             //Total size to remove, and size removed thus far
             let size_to_remove = self.current_size_bytes / 4;
             let size_removed_master = Arc::new(AtomicU64::new(0));
+            let files_removed_master = Arc::new(AtomicU64::new(0));
 
             //Items removed from our priority queue
             let removed_master = Arc::new(RwLock::new(PriorityQueue::new()));
@@ -132,12 +196,19 @@ impl CacheManager {
             //Create our i/o thread pool, execute until we succeed or run out of items to remove
             for _ in 0..WORKER_COUNT {
                 let size_removed = size_removed_master.clone();
+                let files_removed = files_removed_master.clone();
                 let header = header_master.clone();
                 let removed = removed_master.clone();
                 handles.push(async move {
                     while size_removed.load(Ordering::Relaxed) < size_to_remove {
                         let data = header.write().await.cache.pop();
                         if let Some((hash, (priority, file_size))) = data {
+                            //Pinned items are kept, and returned to the queue once trimming is complete
+                            if header.read().await.pinned.contains(&hash) {
+                                removed.write().await.push(hash, (priority, file_size));
+                                continue;
+                            }
+
                             let file_name = hex::encode(hash);
                             //Check if file exists, and if it does remove it
                             let file_name_string = format!("{}.wav", file_name);
@@ -149,6 +220,7 @@ impl CacheManager {
                                         Ok(_) => {
                                             size_removed
                                                 .fetch_add(file_size as u64, Ordering::Relaxed);
+                                            files_removed.fetch_add(1, Ordering::Relaxed);
                                         }
                                         Err(e) => error!(
                                             "failed to remove cached file due to error {}",
@@ -183,7 +255,145 @@ impl CacheManager {
             self_ref.current_size_bytes -= size_removed_master.load(Ordering::Relaxed);
             let mut removed = removed_master.write_owned().await;
             self_ref.cache.append(&mut removed);
+
+            report.files_removed = files_removed_master.load(Ordering::Relaxed);
+            report.bytes_removed = size_removed_master.load(Ordering::Relaxed);
+            self_ref.evicted_files_total += report.files_removed;
+        }
+        report
+    }
+
+    /// Collect a snapshot of the current state of the cache.
+    fn stats(&self) -> CacheStats {
+        let mut most_used: Vec<(&[u8; 32], i32)> = self
+            .cache
+            .iter()
+            .map(|(hash, (priority, _))| (hash, *priority))
+            .collect();
+        most_used.sort_by_key(|(_, priority)| *priority);
+
+        CacheStats {
+            current_size_bytes: self.current_size_bytes,
+            max_allowed_size_bytes: self.max_allowed_size_bytes,
+            entries: self.cache.len(),
+            pinned: self.pinned.len(),
+            evicted_files_total: self.evicted_files_total,
+            most_used: most_used
+                .into_iter()
+                .take(MOST_USED_COUNT)
+                .map(|(hash, priority)| CacheUsage {
+                    hash: hex::encode(hash),
+                    uses: (i32::MAX - priority) as u32 + 1,
+                })
+                .collect(),
+        }
+    }
+
+    /// Remove every file in the cache which matches the provided filter on its file stem, regardless of extension.
+    async fn remove_files<F>(&mut self, filter: F) -> PurgeReport
+    where
+        F: Fn(&str) -> bool,
+    {
+        let mut report = PurgeReport::default();
+
+        let mut entries = match tokio::fs::read_dir(&self.cache_path).await {
+            Ok(e) => e,
+            Err(e) => {
+                error!("unable to read files in cache due to {}", e);
+                return report;
+            }
+        };
+
+        loop {
+            let entry = match entries.next_entry().await {
+                Ok(Some(entry)) => entry,
+                Ok(None) => break,
+                Err(e) => {
+                    error!("unable to read file in cache due to {}", e);
+                    break;
+                }
+            };
+
+            let path = entry.path();
+            match entry.file_name().to_str() {
+                Some(f) if self.restricted_files.contains(f) => continue,
+                Some(_) => {}
+                None => continue,
+            }
+            match path.file_stem().and_then(|s| s.to_str()) {
+                Some(stem) if filter(stem) => {}
+                _ => continue,
+            }
+
+            let md = match entry.metadata().await {
+                Ok(md) if md.is_file() => md,
+                Ok(_) => continue,
+                Err(e) => {
+                    error!("failed to read metadata of file {:?} error {}", &path, e);
+                    continue;
+                }
+            };
+
+            match tokio::fs::remove_file(&path).await {
+                Ok(_) => {
+                    report.files_removed += 1;
+                    report.bytes_removed += md.len();
+                }
+                Err(e) => error!("failed to remove cached file due to error {}", e),
+            }
+        }
+
+        report
+    }
+
+    /// Remove the items with the provided hashes from the cache, even if they are pinned, alongside any files named by those hashes in another format.
+    pub async fn purge(&mut self, hashes: &[[u8; 32]]) -> PurgeReport {
+        for hash in hashes {
+            if let Some((_, (_, file_size))) = self.cache.remove(hash) {
+                self.current_size_bytes = self.current_size_bytes.saturating_sub(file_size as u64);
+            }
         }
+
+        let file_stems: HashSet<String> = hashes.iter().map(hex::encode).collect();
+        self.remove_files(|stem| file_stems.contains(stem)).await
+    }
+
+    /// Remove every item from the cache, other than those which are pinned.
+    pub async fn purge_all(&mut self) -> PurgeReport {
+        let pinned = &self.pinned;
+        self.cache = std::mem::take(&mut self.cache)
+            .into_iter()
+            .filter(|(hash, _)| pinned.contains(hash))
+            .collect();
+        self.current_size_bytes = self
+            .cache
+            .iter()
+            .map(|(_, (_, file_size))| *file_size as u64)
+            .sum();
+
+        let pinned: HashSet<String> = self.pinned.iter().map(hex::encode).collect();
+        self.remove_files(|stem| !pinned.contains(stem)).await
+    }
+
+    /// Pin or unpin the provided hashes, recording the pins in the cache folder so they persist between restarts.
+    /// Returns the number of hashes which changed.
+    pub async fn pin(&mut self, hashes: &[[u8; 32]], pinned: bool) -> usize {
+        let changed = hashes
+            .iter()
+            .filter(|hash| match pinned {
+                true => self.pinned.insert(**hash),
+                false => self.pinned.remove(*hash),
+            })
+            .count();
+
+        let mut pins: Vec<String> = self.pinned.iter().map(hex::encode).collect();
+        pins.sort_unstable();
+        let pins: String = pins.into_iter().map(|p| p + "\n").collect();
+        if let Err(e) = tokio::fs::write(self.cache_path.join(PINS_FILE), pins).await {
+            error!("failed to record pinned files due to error {}", e);
+        }
+
+        changed
     }
 
     /// move the process into it's own thread
@@ -220,6 +430,21 @@ impl CacheManager {
                                         _ => count += 1,
                                     }
                                 }
+                                CacheAction::Stats(tx) => {
+                                    let _ = tx.send(self.stats());
+                                }
+                                CacheAction::Purge(hashes, tx) => {
+                                    let _ = tx.send(self.purge(&hashes).await);
+                                }
+                                CacheAction::Pin(hashes, pinned, tx) => {
+                                    let _ = tx.send(self.pin(&hashes, pinned).await);
+                                }
+                                CacheAction::PurgeAll(tx) => {
+                                    let _ = tx.send(self.purge_all().await);
+                                }
+                                CacheAction::Enforce(tx) => {
+                                    let _ = tx.send(self.enforce_cache_size().await);
+                                }
                                 CacheAction::Close => {
                                     self.rx.close();
                                 }
@@ -236,9 +461,19 @@ impl CacheManager {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug)]
 pub enum CacheAction {
     Used(([u8; 32], u32)),
+    /// Request a snapshot of the current state of the cache.
+    Stats(oneshot::Sender<CacheStats>),
+    /// Remove the items with the provided hashes, in any format, from the cache.
+    Purge(Vec<[u8; 32]>, oneshot::Sender<PurgeReport>),
+    /// Remove every item from the cache, other than those which are pinned.
+    PurgeAll(oneshot::Sender<PurgeReport>),
+    /// Pin, or unpin, the items with the provided hashes.
+    Pin(Vec<[u8; 32]>, bool, oneshot::Sender<usize>),
+    /// Trim the cache if it is larger than the maximum allowed size.
+    Enforce(oneshot::Sender<PurgeReport>),
     Close,
 }
 
@@ -298,6 +533,53 @@ impl Cache {
         Ok(())
     }
 
+    /// Collect a snapshot of the current state of the cache.
+    pub async fn stats(&self) -> Result<CacheStats, Box<dyn std::error::Error>> {
+        let (tx, rx) = oneshot::channel();
+        self.sender.send(CacheAction::Stats(tx))?;
+        Ok(rx.await?)
+    }
+
+    /// Remove the items with the provided hashes from the cache, in any format.
+    /// A phrase is stored under many hashes, see `PhrasePackage::cached_stems()`.
+    pub async fn purge(
+        &self,
+        hashes: Vec<[u8; 32]>,
+    ) -> Result<PurgeReport, Box<dyn std::error::Error>> {
+        let (tx, rx) = oneshot::channel();
+        self.sender.send(CacheAction::Purge(hashes, tx))?;
+        Ok(rx.await?)
+    }
+
+    /// Remove every item from the cache, other than those which are pinned.
+    pub async fn purge_all(&self) -> Result<PurgeReport, Box<dyn std::error::Error>> {
+        let (tx, rx) = oneshot::channel();
+        self.sender.send(CacheAction::PurgeAll(tx))?;
+        Ok(rx.await?)
+    }
+
+    /// Pin the items with the provided hashes, so they are never trimmed from the cache or removed by `purge_all()`.
+    /// They may still be removed with `purge()`. Returns the number of hashes which were not already pinned.
+    pub async fn pin(&self, hashes: Vec<[u8; 32]>) -> Result<usize, Box<dyn std::error::Error>> {
+        let (tx, rx) = oneshot::channel();
+        self.sender.send(CacheAction::Pin(hashes, true, tx))?;
+        Ok(rx.await?)
+    }
+
+    /// Unpin the items with the provided hashes. Returns the number of hashes which were pinned.
+    pub async fn unpin(&self, hashes: Vec<[u8; 32]>) -> Result<usize, Box<dyn std::error::Error>> {
+        let (tx, rx) = oneshot::channel();
+        self.sender.send(CacheAction::Pin(hashes, false, tx))?;
+        Ok(rx.await?)
+    }
+
+    /// Trim the cache immediately if it is larger than the maximum allowed size.
+    pub async fn enforce(&self) -> Result<PurgeReport, Box<dyn std::error::Error>> {
+        let (tx, rx) = oneshot::channel();
+        self.sender.send(CacheAction::Enforce(tx))?;
+        Ok(rx.await?)
+    }
+
     /// Attempt to close the cache, and destroy it. This will cause all future
     /// messages to the cache to return an error. This should only be called
     /// when closing the entire api.
@@ -323,12 +605,14 @@ impl<'r> FromRequest<'r> for Cache {
 
 #[cfg(test)]
 mod test {
-    use crate::Cache;
+    use crate::{Cache, CacheManager};
     use config::{Config, PathType};
-    use rocket::{get, http::Status, local::blocking::Client, routes, uri};
+    use rocket::{
+        get, http::Status, local::blocking::Client, routes, tokio::runtime::Runtime, uri,
+    };
     use std::path::PathBuf;
-    use utils::sha_256_hash;
     use utils::test_utils::AlteredToml;
+    use utils::{generate_random_alphanumeric, sha_256_hash};
 
     #[get("/")]
     async fn hello_world(cache: Cache) -> &'static str {
@@ -349,6 +633,49 @@ mod test {
         // TODO
     }
 
+    /// Test that pinned items are never trimmed from the cache, or removed when the entire cache is purged
+    #[test]
+    fn pinned_items() {
+        let path = std::env::temp_dir().join(format!("cache-{}", generate_random_alphanumeric(8)));
+        std::fs::create_dir(&path).unwrap();
+        let hashes: Vec<String> = (0..3)
+            .map(|i| sha_256_hash(&format!("phrase {}", i)))
+            .collect();
+        for hash in hashes.iter() {
+            std::fs::write(path.join(format!("{}.wav", hash)), [0; 100]).unwrap();
+        }
+
+        let (mut manager, _tx) = CacheManager::new(path.clone(), 0).unwrap();
+        let mut pins: Vec<[u8; 32]> = vec![[0; 32]; 2];
+        hex::decode_to_slice(&hashes[0], &mut pins[0]).unwrap();
+        hex::decode_to_slice(&hashes[1], &mut pins[1]).unwrap();
+
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            assert_eq!(manager.pin(&pins, true).await, 2);
+            let report = manager.enforce_cache_size().await;
+            assert_eq!(report.files_removed, 1);
+            assert_eq!(manager.stats().entries, 2);
+
+            let report = manager.purge_all().await;
+            assert_eq!(report.files_removed, 0);
+        });
+        assert!(path.join(format!("{}.wav", hashes[0])).exists());
+        assert!(path.join(format!("{}.wav", hashes[1])).exists());
+        assert!(!path.join(format!("{}.wav", hashes[2])).exists());
+
+        //Pins persist between restarts
+        let (mut manager, _tx) = CacheManager::new(path.clone(), 0).unwrap();
+        assert_eq!(manager.stats().pinned, 2);
+        rt.block_on(async {
+            assert_eq!(manager.pin(&pins[..1], false).await, 1);
+            let report = manager.purge_all().await;
+            assert_eq!(report.files_removed, 1);
+        });
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
     /// Test that creating and attaching a fairing does not cause a panic
     #[test]
     fn rocket_fairing() {
@@ -0,0 +1,486 @@
+//! Matching of phrases against the blacklist. Every blocked phrase is compiled into a single `RegexSet`
+//! when the configuration is loaded, so a phrase is checked against the whole blacklist in one pass.
+//! Wordlists, which may hold thousands of phrases, are instead matched with Aho-Corasick.
+
+use std::{collections::HashMap, ops::Range, str::FromStr};
+
+use aho_corasick::{AhoCorasick, AhoCorasickBuilder};
+use regex::{Regex, RegexBuilder, RegexSet, RegexSetBuilder};
+use rocket::{serde::Deserialize, FromFormField};
+
+use crate::error::ConfigError;
+
+/// How the phrases of the blacklist are matched against a phrase to be converted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum BlacklistMode {
+    /// Blocked phrases match anywhere within the phrase, including within other words.
+    /// The phrase is padded with a space either side, so that `" test "` only matches the whole word.
+    Substring,
+    /// Blocked phrases only match whole words, bounded by the start or end of the phrase or by a non-word character.
+    Word,
+    /// Blocked phrases are regular expressions.
+    Regex,
+}
+
+impl FromStr for BlacklistMode {
+    type Err = ConfigError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "substring" => Ok(BlacklistMode::Substring),
+            "word" => Ok(BlacklistMode::Word),
+            "regex" => Ok(BlacklistMode::Regex),
+            s => Err(ConfigError::UnknownBlacklistMode(s.to_owned())),
+        }
+    }
+}
+
+/// What is done with a phrase which contains a blacklisted phrase.
+/// Set for the whole api with `BLACKLIST_POLICY`, and may be chosen by clients for a single request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, FromFormField)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum BlacklistPolicy {
+    /// The request is rejected.
+    #[field(value = "reject")]
+    Reject,
+    /// The blacklisted words are removed from the phrase before it is read.
+    #[field(value = "skip")]
+    Skip,
+    /// The blacklisted words are replaced by a tone of the same duration.
+    #[field(value = "bleep")]
+    Bleep,
+}
+
+impl FromStr for BlacklistPolicy {
+    type Err = ConfigError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reject" => Ok(BlacklistPolicy::Reject),
+            "skip" => Ok(BlacklistPolicy::Skip),
+            "bleep" => Ok(BlacklistPolicy::Bleep),
+            s => Err(ConfigError::UnknownBlacklistPolicy(s.to_owned())),
+        }
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Whether the provided char, adjacent to a match, ends a word.
+fn is_boundary(c: Option<char>) -> bool {
+    !matches!(c, Some(c) if is_word_char(c))
+}
+
+/// Lowercase a phrase, alongside the offset in the original phrase of every byte in the lowercase phrase.
+/// A trailing offset is included for the end of the phrase.
+fn lowercase_with_offsets(phrase: &str) -> (String, Vec<usize>) {
+    let mut lower = String::with_capacity(phrase.len());
+    let mut offsets = Vec::with_capacity(phrase.len() + 1);
+    for (i, c) in phrase.char_indices() {
+        for l in c.to_lowercase() {
+            lower.push(l);
+            for _ in 0..l.len_utf8() {
+                offsets.push(i);
+            }
+        }
+    }
+    offsets.push(phrase.len());
+    (lower, offsets)
+}
+
+/// Literal phrases loaded from wordlist files.
+#[derive(Debug, Clone)]
+struct Wordlist {
+    phrases: Vec<String>,
+    matcher: AhoCorasick,
+}
+
+impl Wordlist {
+    fn new(phrases: Vec<String>, case_insensitive: bool) -> Self {
+        let patterns = phrases.iter().map(|phrase| match case_insensitive {
+            true => phrase.to_lowercase(),
+            false => phrase.clone(),
+        });
+        Wordlist {
+            matcher: AhoCorasickBuilder::new().build(patterns),
+            phrases,
+        }
+    }
+
+    /// Every match of this wordlist in the provided phrase, optionally only matching whole words.
+    fn matches<'a>(
+        &'a self,
+        phrase: &'a str,
+        whole_words: bool,
+    ) -> impl Iterator<Item = aho_corasick::Match> + 'a {
+        self.matcher.find_overlapping_iter(phrase).filter(move |m| {
+            !whole_words
+                || (is_boundary(phrase[..m.start()].chars().next_back())
+                    && is_boundary(phrase[m.end()..].chars().next()))
+        })
+    }
+
+    /// Returns the first phrase of this wordlist found in the provided phrase, optionally only matching whole words.
+    fn find(&self, phrase: &str, whole_words: bool) -> Option<&str> {
+        self.matches(phrase, whole_words)
+            .next()
+            .map(|m| self.phrases[m.pattern()].as_str())
+    }
+}
+
+/// The phrases which are not allowed on this api.
+#[derive(Debug, Clone)]
+pub struct Blacklist {
+    phrases: Vec<String>,
+    mode: BlacklistMode,
+    case_insensitive: bool,
+    set: RegexSet,
+    /// Each phrase compiled separately, used to locate a phrase once the set has found it.
+    regexes: Vec<Regex>,
+    /// Phrases from wordlists which apply to every language.
+    words: Option<Wordlist>,
+    /// Phrases from wordlists which only apply to a single language, keyed by its iso code.
+    lang_words: HashMap<String, Wordlist>,
+}
+
+impl Blacklist {
+    /// Compile a blacklist, failing if a phrase is not a valid regular expression in `BlacklistMode::Regex`.
+    pub fn new(
+        phrases: Vec<String>,
+        mode: BlacklistMode,
+        case_insensitive: bool,
+    ) -> Result<Self, regex::Error> {
+        let patterns: Vec<String> = phrases
+            .iter()
+            .map(|phrase| match mode {
+                BlacklistMode::Substring => regex::escape(phrase),
+                BlacklistMode::Word => {
+                    //Only require a boundary where the phrase begins or ends with a word
+                    let phrase = phrase.trim();
+                    let boundary = |c: Option<char>| match c {
+                        Some(c) if is_word_char(c) => r"\b",
+                        _ => "",
+                    };
+                    format!(
+                        "{}{}{}",
+                        boundary(phrase.chars().next()),
+                        regex::escape(phrase),
+                        boundary(phrase.chars().next_back())
+                    )
+                }
+                BlacklistMode::Regex => phrase.clone(),
+            })
+            .collect();
+        let set = RegexSetBuilder::new(&patterns)
+            .case_insensitive(case_insensitive)
+            .build()?;
+        let regexes = patterns
+            .iter()
+            .map(|p| {
+                RegexBuilder::new(p)
+                    .case_insensitive(case_insensitive)
+                    .build()
+            })
+            .collect::<Result<Vec<Regex>, regex::Error>>()?;
+
+        Ok(Blacklist {
+            phrases,
+            mode,
+            case_insensitive,
+            set,
+            regexes,
+            words: None,
+            lang_words: HashMap::default(),
+        })
+    }
+
+    /// Add phrases loaded from wordlists, which apply to every language if no language is provided.
+    /// Wordlist phrases are always matched literally, as whole words in `BlacklistMode::Word` and as substrings otherwise.
+    pub fn with_wordlist(mut self, lang: Option<&str>, mut phrases: Vec<String>) -> Self {
+        let existing = match lang {
+            Some(lang) => self.lang_words.remove(lang),
+            None => self.words.take(),
+        };
+        if let Some(existing) = existing {
+            phrases.splice(0..0, existing.phrases);
+        }
+
+        let wordlist = Wordlist::new(phrases, self.case_insensitive);
+        match lang {
+            Some(lang) => {
+                self.lang_words.insert(lang.to_owned(), wordlist);
+            }
+            None => self.words = Some(wordlist),
+        }
+        self
+    }
+
+    /// The phrases of this blacklist, as configured.
+    pub fn phrases(&self) -> &[String] {
+        &self.phrases
+    }
+
+    /// Returns the first blocked phrase which is present in the provided phrase, when read in the provided language.
+    /// The phrases of `BLACKLISTED_PHRASES` are checked first in the order configured, then those of any wordlists.
+    pub fn find(&self, phrase: &str, lang: &str) -> Option<&str> {
+        let matches = match self.mode {
+            BlacklistMode::Substring => self.set.matches(&format!(" {} ", phrase)),
+            BlacklistMode::Word | BlacklistMode::Regex => self.set.matches(phrase),
+        };
+        if let Some(i) = matches.iter().next() {
+            return Some(self.phrases[i].trim());
+        }
+
+        let lang_words = self.lang_words.get(lang);
+        if self.words.is_none() && lang_words.is_none() {
+            return None;
+        }
+        let phrase = match self.case_insensitive {
+            true => phrase.to_lowercase(),
+            false => phrase.to_owned(),
+        };
+        let whole_words = self.mode == BlacklistMode::Word;
+        self.words
+            .iter()
+            .chain(lang_words)
+            .find_map(|words| words.find(&phrase, whole_words))
+    }
+
+    /// The text the blocked phrases are matched against, alongside the offset of the phrase within it.
+    /// Substring matches are made against the padded phrase, so must be shifted back.
+    fn haystack(&self, phrase: &str) -> (String, usize) {
+        match self.mode {
+            BlacklistMode::Substring => (format!(" {} ", phrase), 1),
+            BlacklistMode::Word | BlacklistMode::Regex => (phrase.to_owned(), 0),
+        }
+    }
+
+    /// Returns the first blocked phrase which is present in the provided phrase, but which `matches()` is unable to
+    /// locate, as it only matched an empty or blank part of the phrase. This is only possible in `BlacklistMode::Regex`,
+    /// with patterns such as `^`. Such a phrase can't be skipped or bleeped, so must be rejected.
+    pub fn unlocatable(&self, phrase: &str) -> Option<&str> {
+        let (haystack, shift) = self.haystack(phrase);
+        self.set.matches(&haystack).iter().find_map(|i| {
+            let located = self.regexes[i].find_iter(&haystack).any(|m| {
+                let start = m.start().saturating_sub(shift).min(phrase.len());
+                let end = m.end().saturating_sub(shift).min(phrase.len());
+                !phrase[start..end].trim().is_empty()
+            });
+            (!located).then(|| self.phrases[i].trim())
+        })
+    }
+
+    /// Locates every blocked phrase within the provided phrase, when read in the provided language.
+    /// Returns the byte ranges of the matches in order, with overlapping matches merged and surrounding whitespace excluded.
+    pub fn matches(&self, phrase: &str, lang: &str) -> Vec<Range<usize>> {
+        let mut ranges: Vec<Range<usize>> = vec![];
+
+        let (haystack, shift) = self.haystack(phrase);
+        for i in self.set.matches(&haystack).iter() {
+            ranges.extend(self.regexes[i].find_iter(&haystack).map(|m| {
+                let start = m.start().saturating_sub(shift).min(phrase.len());
+                let end = m.end().saturating_sub(shift).min(phrase.len());
+                start..end
+            }));
+        }
+
+        let lang_words = self.lang_words.get(lang);
+        let whole_words = self.mode == BlacklistMode::Word;
+        if self.case_insensitive {
+            let (lower, offsets) = lowercase_with_offsets(phrase);
+            for words in self.words.iter().chain(lang_words) {
+                ranges.extend(
+                    words
+                        .matches(&lower, whole_words)
+                        .map(|m| offsets[m.start()]..offsets[m.end()]),
+                );
+            }
+        } else {
+            for words in self.words.iter().chain(lang_words) {
+                ranges.extend(
+                    words
+                        .matches(phrase, whole_words)
+                        .map(|m| m.start()..m.end()),
+                );
+            }
+        }
+
+        //Exclude surrounding whitespace, then merge overlapping matches
+        let mut ranges: Vec<Range<usize>> = ranges
+            .into_iter()
+            .filter_map(|r| {
+                let text = &phrase[r.clone()];
+                let start = r.start + (text.len() - text.trim_start().len());
+                let end = r.end - (text.len() - text.trim_end().len());
+                (start < end).then_some(start..end)
+            })
+            .collect();
+        ranges.sort_by_key(|r| r.start);
+
+        let mut merged: Vec<Range<usize>> = vec![];
+        for r in ranges {
+            match merged.last_mut() {
+                Some(last) if r.start <= last.end => last.end = last.end.max(r.end),
+                _ => merged.push(r),
+            }
+        }
+        merged
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Blacklist, BlacklistMode, BlacklistPolicy};
+
+    fn blacklist(phrases: &[&str], mode: BlacklistMode, case_insensitive: bool) -> Blacklist {
+        Blacklist::new(strings(phrases), mode, case_insensitive).expect("a valid blacklist")
+    }
+
+    fn strings(phrases: &[&str]) -> Vec<String> {
+        phrases.iter().map(|p| p.to_string()).collect()
+    }
+
+    #[test]
+    fn substring_mode() {
+        let b = blacklist(&["test", " things "], BlacklistMode::Substring, false);
+        assert_eq!(b.find("hello this is a test", "en"), Some("test"));
+        assert_eq!(b.find("testing123", "en"), Some("test"));
+        assert_eq!(b.find("things", "en"), Some("things"));
+        assert_eq!(b.find("vibes things my guy", "en"), Some("things"));
+        assert_eq!(b.find("somethings", "en"), None);
+        assert_eq!(b.find("t e s t", "en"), None);
+        assert_eq!(b.find("TEST", "en"), None);
+
+        //The first phrase configured is reported
+        assert_eq!(b.find("things and testing", "en"), Some("test"));
+    }
+
+    #[test]
+    fn word_mode() {
+        let b = blacklist(&["test", "kia ora"], BlacklistMode::Word, false);
+        assert_eq!(b.find("test", "en"), Some("test"));
+        assert_eq!(b.find("hi test hi", "en"), Some("test"));
+        assert_eq!(b.find("a test, maybe", "en"), Some("test"));
+        assert_eq!(b.find("(test)", "en"), Some("test"));
+        assert_eq!(b.find("testing123", "en"), None);
+        assert_eq!(b.find("contest", "en"), None);
+        assert_eq!(b.find("kia ora koutou", "en"), Some("kia ora"));
+
+        //Word boundaries are unicode aware, macrons are part of a word
+        let b = blacklist(&["maor"], BlacklistMode::Word, false);
+        assert_eq!(b.find("māori", "en"), None);
+        let b = blacklist(&["tāne"], BlacklistMode::Word, false);
+        assert_eq!(b.find("he tāne", "en"), Some("tāne"));
+        assert_eq!(b.find("tānenui", "en"), None);
+    }
+
+    #[test]
+    fn case_insensitive() {
+        let b = blacklist(&["test"], BlacklistMode::Substring, true);
+        assert_eq!(b.find("TEST", "en"), Some("test"));
+        assert_eq!(b.find("Testing", "en"), Some("test"));
+
+        let b = blacklist(&["tāne"], BlacklistMode::Word, true);
+        assert_eq!(b.find("TĀNE", "en"), Some("tāne"));
+    }
+
+    #[test]
+    fn regex_mode() {
+        let b = blacklist(&[r"^\d+$", r"t[e3]st"], BlacklistMode::Regex, true);
+        assert_eq!(b.find("12345", "en"), Some(r"^\d+$"));
+        assert_eq!(b.find("123 go", "en"), None);
+        assert_eq!(b.find("a T3ST", "en"), Some(r"t[e3]st"));
+
+        let invalid = vec![String::from("(unclosed")];
+        assert!(Blacklist::new(invalid, BlacklistMode::Regex, false).is_err());
+    }
+
+    #[test]
+    fn parse_mode() {
+        assert_eq!(
+            "word".parse::<BlacklistMode>().unwrap(),
+            BlacklistMode::Word
+        );
+        assert!("words".parse::<BlacklistMode>().is_err());
+    }
+
+    #[test]
+    fn wordlists() {
+        let b = blacklist(&["test"], BlacklistMode::Word, true)
+            .with_wordlist(None, strings(&["global"]))
+            .with_wordlist(Some("en"), strings(&["mate"]))
+            .with_wordlist(Some("mi"), strings(&["tāne"]))
+            .with_wordlist(Some("en"), strings(&["cheers"]));
+
+        assert_eq!(b.find("a Test", "mi"), Some("test"));
+        assert_eq!(b.find("GLOBAL warming", "mi"), Some("global"));
+        assert_eq!(b.find("globally", "en"), None);
+
+        //Wordlists of a language only apply to phrases in that language
+        assert_eq!(b.find("kia ora mate", "en"), Some("mate"));
+        assert_eq!(b.find("kia ora mate", "mi"), None);
+        assert_eq!(b.find("he TĀNE", "mi"), Some("tāne"));
+        assert_eq!(b.find("he tāne", "en"), None);
+
+        //Wordlists for the same language are combined
+        assert_eq!(b.find("cheers", "en"), Some("cheers"));
+        assert_eq!(b.find("mate", "en"), Some("mate"));
+
+        //A later overlapping match is found if an earlier one is not a whole word
+        let b =
+            blacklist(&[], BlacklistMode::Word, false).with_wordlist(None, strings(&["ab", "abc"]));
+        assert_eq!(b.find("abc", "en"), Some("abc"));
+
+        //Outside of word mode, wordlist phrases match anywhere
+        let b = blacklist(&[], BlacklistMode::Regex, false).with_wordlist(None, strings(&["a.c"]));
+        assert_eq!(b.find("xa.cx", "en"), Some("a.c"));
+        assert_eq!(b.find("abc", "en"), None);
+    }
+
+    #[test]
+    fn match_ranges() {
+        let b = blacklist(&["test", " things "], BlacklistMode::Substring, true);
+        assert_eq!(b.matches("a TEST of things", "en"), vec![2..6, 10..16]);
+        assert_eq!(b.matches("things", "en"), vec![0..6]);
+        assert_eq!(b.matches("testtest", "en"), vec![0..8]);
+        assert!(b.matches("hello", "en").is_empty());
+
+        let b = blacklist(&["test", "st of"], BlacklistMode::Word, true);
+        assert_eq!(b.matches("test test, contest", "en"), vec![0..4, 5..9]);
+
+        //Overlapping matches are merged
+        let b = blacklist(&["test", "st of"], BlacklistMode::Substring, true);
+        assert_eq!(b.matches("a test of", "en"), vec![2..9]);
+
+        //Offsets account for chars which change length when lowercased
+        let b =
+            blacklist(&[], BlacklistMode::Word, true).with_wordlist(Some("mi"), strings(&["tāne"]));
+        assert_eq!(b.matches("ȺȺ TĀNE", "mi"), vec![5..10]);
+        assert!(b.matches("ȺȺ TĀNE", "en").is_empty());
+    }
+
+    #[test]
+    fn unlocatable_matches() {
+        let b = blacklist(&["^", "te+st"], BlacklistMode::Regex, false);
+        assert_eq!(b.find("hello", "en"), Some("^"));
+        assert!(b.matches("hello", "en").is_empty());
+        assert_eq!(b.unlocatable("hello"), Some("^"));
+
+        let b = blacklist(&["te+st", r"\b"], BlacklistMode::Regex, false);
+        assert_eq!(b.unlocatable("teest"), Some(r"\b"));
+
+        let b = blacklist(&["te+st"], BlacklistMode::Regex, false);
+        assert_eq!(b.unlocatable("a teest"), None);
+        assert_eq!(b.unlocatable("hello"), None);
+    }
+
+    #[test]
+    fn parse_policy() {
+        assert_eq!(
+            "bleep".parse::<BlacklistPolicy>().unwrap(),
+            BlacklistPolicy::Bleep
+        );
+        assert!("beep".parse::<BlacklistPolicy>().is_err());
+    }
+}
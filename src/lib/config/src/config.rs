@@ -4,18 +4,36 @@ use std::{
     collections::{HashMap, HashSet},
     convert::Infallible,
     env::var,
-    path::PathBuf,
+    path::{Path, PathBuf},
     str::FromStr,
 };
 
 use rocket::{
+    error,
     fairing::AdHoc,
+    http::Status,
     request::{self, FromRequest},
-    Request,
+    warn, Request,
 };
 
-use crate::error::ConfigError;
-use crate::models::Language;
+use crate::blacklist::{Blacklist, BlacklistMode, BlacklistPolicy};
+use crate::error::{AuthError, ConfigError};
+use crate::models::{Admin, Language, User};
+use crate::validation::{self, ConfigIssue, Severity};
+
+/// The header through which users provide their api key.
+pub const API_KEY_HEADER: &str = "X-Api-Key";
+
+/// The query parameter through which users may provide their api key, for clients which cannot set headers
+/// such as an `<audio>` element.
+pub const API_KEY_QUERY: &str = "api_key";
+
+/// Collects the api key provided with a request, preferring the header over the query parameter.
+pub fn api_key<'r>(req: &'r Request<'_>) -> Option<&'r str> {
+    req.headers()
+        .get_one(API_KEY_HEADER)
+        .or_else(|| req.query_value::<&str>(API_KEY_QUERY).and_then(|k| k.ok()))
+}
 
 //General Todos
 //TODO: Macroise a lot of the initalisation code to clean it up.
@@ -42,6 +60,27 @@ impl PathType {
     }
 }
 
+/// How clients of the api are told apart for the purposes of rate limiting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, rocket::serde::Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum RateLimitBy {
+    /// Limit each api key separately, falling back to the ip address if no key is provided.
+    Key,
+    /// Limit each ip address separately, regardless of the api key provided.
+    Ip,
+}
+
+impl FromStr for RateLimitBy {
+    type Err = ConfigError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "key" => Ok(RateLimitBy::Key),
+            "ip" => Ok(RateLimitBy::Ip),
+            s => Err(ConfigError::UnknownRateLimitBy(s.to_owned())),
+        }
+    }
+}
+
 /// Opens a toml file, and attempts to load the toml::value as specified in the provided &str.
 fn load_from_toml(name: &str, path: &PathBuf) -> Result<toml::Value, String> {
     let file_path = PathType::General.get_path(path);
@@ -114,22 +153,29 @@ fn load_table(file_path: &PathBuf, table_name: &str) -> Result<toml::value::Tabl
         .get(table_name)
         .unwrap_or_else(|| {
             panic!(
-                "Unable to parse {}, no langs provided!",
-                file_path.to_string_lossy()
+                "Unable to parse {}, no {} provided!",
+                file_path.to_string_lossy(),
+                table_name
             )
         })
         .as_table()
-        .unwrap_or_else(|| panic!("lang tag is not a table in {}", file_path.to_string_lossy()))
+        .unwrap_or_else(|| {
+            panic!(
+                "{} tag is not a table in {}",
+                table_name,
+                file_path.to_string_lossy()
+            )
+        })
         .to_owned();
 
     Ok(table)
 }
 
-fn load_supported_langs(path: &PathBuf) -> Result<HashMap<String, Language>, ConfigError> {
+fn load_supported_langs(path: &PathBuf) -> Result<Vec<Language>, ConfigError> {
     let file_path = PathType::Langs.get_path(path);
     let languages = load_table(&file_path, "lang")?;
 
-    let mut map: HashMap<String, Language> = HashMap::default();
+    let mut res = vec![];
     let keys: Vec<&String> = languages.keys().into_iter().collect();
     for key in keys {
         let lang = languages
@@ -206,13 +252,143 @@ fn load_supported_langs(path: &PathBuf) -> Result<HashMap<String, Language>, Con
             })
             .to_owned();
 
+        let blacklist_files = match lang.get("blacklist_files") {
+            Some(files) => files
+                .as_array()
+                .and_then(|files| {
+                    files
+                        .iter()
+                        .map(|f| f.as_str().map(|f| f.to_owned()))
+                        .collect::<Option<Vec<String>>>()
+                })
+                .unwrap_or_else(|| {
+                    panic!(
+                        "{}'s blacklist_files is not an array of strings in {}",
+                        key,
+                        file_path.to_string_lossy()
+                    )
+                }),
+            None => vec![],
+        };
+
+        res.push(Language {
+            display_name: key.clone(),
+            enabled,
+            festival_code,
+            iso_691_code,
+            blacklist_files,
+        });
+    }
+
+    Ok(res)
+}
+
+/// Loads all users from `users.toml`, keyed by their api key.
+fn load_users(path: &PathBuf) -> Result<HashMap<String, User>, ConfigError> {
+    let file_path = PathType::Users.get_path(path);
+    let users = load_table(&file_path, "user")?;
+
+    let mut map: HashMap<String, User> = HashMap::default();
+    let mut ids: HashSet<i32> = HashSet::default();
+    for (name, user) in users.iter() {
+        let user = user.as_table().unwrap_or_else(|| {
+            panic!(
+                "Unable to parse {} as table from {}",
+                name,
+                file_path.to_string_lossy()
+            )
+        });
+
+        let id = user
+            .get("id")
+            .unwrap_or_else(|| {
+                panic!(
+                    "Unable to parse id on {} from {}",
+                    name,
+                    file_path.to_string_lossy()
+                )
+            })
+            .as_integer()
+            .unwrap_or_else(|| {
+                panic!(
+                    "{}'s id is not an integer in {}",
+                    name,
+                    file_path.to_string_lossy()
+                )
+            }) as i32;
+
+        let key = user
+            .get("key")
+            .unwrap_or_else(|| {
+                panic!(
+                    "Unable to parse key on {} from {}",
+                    name,
+                    file_path.to_string_lossy()
+                )
+            })
+            .as_str()
+            .unwrap_or_else(|| {
+                panic!(
+                    "{}'s key is not a string in {}",
+                    name,
+                    file_path.to_string_lossy()
+                )
+            })
+            .to_owned();
+
+        let enabled = user
+            .get("enabled")
+            .unwrap_or_else(|| {
+                panic!(
+                    "Unable to parse enabled on {} from {}",
+                    name,
+                    file_path.to_string_lossy()
+                )
+            })
+            .as_bool()
+            .unwrap_or_else(|| {
+                panic!(
+                    "{}'s enabled is not a boolean in {}",
+                    name,
+                    file_path.to_string_lossy()
+                )
+            });
+
+        let admin = match user.get("admin") {
+            Some(a) => a.as_bool().unwrap_or_else(|| {
+                panic!(
+                    "{}'s admin is not a boolean in {}",
+                    name,
+                    file_path.to_string_lossy()
+                )
+            }),
+            None => false,
+        };
+
+        if !ids.insert(id) {
+            panic!(
+                "{}'s id ({}) is used by another user in {}",
+                name,
+                id,
+                file_path.to_string_lossy()
+            );
+        }
+        if map.contains_key(&key) {
+            panic!(
+                "{}'s key is used by another user in {}",
+                name,
+                file_path.to_string_lossy()
+            );
+        }
+
         map.insert(
-            iso_691_code.clone(),
-            Language {
-                display_name: key.clone(),
+            key.clone(),
+            User {
+                id,
+                name: name.clone(),
+                key,
                 enabled,
-                festival_code,
-                iso_691_code,
+                admin,
             },
         );
     }
@@ -322,7 +498,7 @@ fn load_allowed_chars(path: &PathBuf) -> Result<HashSet<char>, ConfigError> {
     Ok(res)
 }
 
-fn load_blacklisted_phrases(path: &PathBuf) -> Result<Vec<String>, ConfigError> {
+fn load_string_array(path: &PathBuf, key: &str) -> Result<Vec<String>, ConfigError> {
     let file_path = PathType::General.get_path(path);
 
     let data = std::fs::read_to_string(&file_path).unwrap_or_else(|e| {
@@ -345,17 +521,13 @@ fn load_blacklisted_phrases(path: &PathBuf) -> Result<Vec<String>, ConfigError>
         .unwrap_or_else(|| panic!("Unable to parse {} as table.", file_path.to_string_lossy()));
 
     let phrases = table
-        .get("BLACKLISTED_PHRASES")
-        .unwrap_or_else(|| {
-            panic!(
-                "Unable to find BLACKLISTED_PHRASES in {}",
-                file_path.to_string_lossy()
-            )
-        })
+        .get(key)
+        .unwrap_or_else(|| panic!("Unable to find {} in {}", key, file_path.to_string_lossy()))
         .as_array()
         .unwrap_or_else(|| {
             panic!(
-                "BLACKLISTED_PHRASES in {} is not an array of strings!",
+                "{} in {} is not an array of strings!",
+                key,
                 file_path.to_string_lossy()
             )
         });
@@ -367,7 +539,8 @@ fn load_blacklisted_phrases(path: &PathBuf) -> Result<Vec<String>, ConfigError>
             .as_str()
             .unwrap_or_else(|| {
                 panic!(
-                    "BLACKLISTED_PHRASES in {} is not an array of strings!",
+                    "{} in {} is not an array of strings!",
+                    key,
                     file_path.to_string_lossy()
                 )
             })
@@ -378,6 +551,26 @@ fn load_blacklisted_phrases(path: &PathBuf) -> Result<Vec<String>, ConfigError>
     Ok(res)
 }
 
+/// Load the phrases of wordlist files, one phrase per line. Blank lines, and lines starting with `#`, are ignored.
+/// Paths are relative to the configuration directory.
+fn load_wordlists(path: &Path, files: &[String]) -> Result<Vec<String>, ConfigError> {
+    let mut res = vec![];
+    for file in files {
+        let file_path = path.join(file);
+        let data = std::fs::read_to_string(&file_path)
+            .map_err(|e| ConfigError::UnreadableWordlist(file_path, e))?;
+
+        res.extend(
+            data.lines()
+                .map(|l| l.trim())
+                .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                .map(|l| l.to_owned()),
+        );
+    }
+    Ok(res)
+}
+
+#[derive(Debug, Clone)]
 pub struct Config {
     /// The name of the api which is sent with certain requests.
     api_name: String,
@@ -385,12 +578,36 @@ pub struct Config {
     /// The path to the cache for storing .wav files.
     cache_path: String,
 
+    /// The path to the database used to record generation requests.
+    database_path: String,
+
     /// The maximum size of of the cache that may be stored on the system.
     max_cache_size: usize,
 
+    /// How long, in seconds, clients may reuse a sound file before revalidating it.
+    cache_control_max_age: u32,
+
+    /// The secret used to sign share links, a random secret is generated on startup if this is empty.
+    share_link_secret: String,
+
+    /// How long, in seconds, a share link remains valid for after it is created.
+    share_link_ttl: u32,
+
     /// The maximum length of a phrase that the api will process.
     word_length_limit: usize,
 
+    /// The maximum number of phrases that may be provided in a single batch request.
+    batch_size_limit: usize,
+
+    /// How clients are told apart when rate limiting, either `key` or `ip`.
+    rate_limit_by: RateLimitBy,
+
+    /// The number of cached phrases a client may request each minute.
+    rate_limit_cached_per_min: u32,
+
+    /// The number of uncached phrases a client may request each minute.
+    rate_limit_generated_per_min: u32,
+
     /// The maximum speed at which a phrase can be read.
     speed_max_val: f32,
 
@@ -406,23 +623,76 @@ pub struct Config {
     /// A hashset of chars that the api will accept as input.
     allowed_chars: HashSet<char>,
 
-    /// A list of phrases that are not allowed on this api.
-    blacklisted_phrases: Vec<String>,
+    /// The phrases that are not allowed on this api, and how they are matched.
+    blacklist: Blacklist,
+
+    /// What is done with phrases containing a blacklisted phrase, unless chosen by the client.
+    blacklist_policy: BlacklistPolicy,
+
+    /// The users who may access the authenticated endpoints of this api, keyed by their api key.
+    users: HashMap<String, User>,
+
+    /// The problems found while validating this configuration.
+    issues: Vec<ConfigIssue>,
 }
 
 impl Config {
     pub fn new(path: PathBuf) -> Result<Self, ConfigError> {
+        let mut issues = vec![];
+
+        let supported_langs = validation::unique_langs(load_supported_langs(&path)?, &mut issues);
+        validation::check_voices(&supported_langs, &mut issues);
+
+        //Blacklisted phrases with chars that are not allowed could never match, so are ignored
+        let allowed_chars = load_allowed_chars(&path)?;
+        let mode = load_env("BLACKLIST_MODE", &path)?;
+        let mut phrases = load_string_array(&path, "BLACKLISTED_PHRASES")?;
+        if mode != BlacklistMode::Regex {
+            phrases = validation::allowed_phrases(phrases, &allowed_chars, &mut issues);
+        }
+        let words = load_wordlists(&path, &load_string_array(&path, "BLACKLIST_FILES")?)?;
+
+        let mut blacklist = Blacklist::new(
+            phrases,
+            mode,
+            load_env("BLACKLIST_CASE_INSENSITIVE", &path)?,
+        )?
+        .with_wordlist(
+            None,
+            validation::allowed_phrases(words, &allowed_chars, &mut issues),
+        );
+        for lang in supported_langs.values() {
+            if !lang.blacklist_files.is_empty() {
+                let words = load_wordlists(&path, &lang.blacklist_files)?;
+                blacklist = blacklist.with_wordlist(
+                    Some(&lang.iso_691_code),
+                    validation::allowed_phrases(words, &allowed_chars, &mut issues),
+                );
+            }
+        }
+
         Ok(Self {
             api_name: load_env("API_NAME", &path)?,
             cache_path: load_env("CACHE_PATH", &path)?,
+            database_path: load_env("DATABASE_PATH", &path)?,
             max_cache_size: load_env("MAX_CACHE_SIZE_MB", &path)?,
+            cache_control_max_age: load_env("CACHE_CONTROL_MAX_AGE", &path)?,
+            share_link_secret: load_env("SHARE_LINK_SECRET", &path)?,
+            share_link_ttl: load_env("SHARE_LINK_TTL_SECS", &path)?,
             word_length_limit: load_env("CHAR_LENGTH_LIMIT", &path)?,
+            batch_size_limit: load_env("BATCH_SIZE_LIMIT", &path)?,
+            rate_limit_by: load_env("RATE_LIMIT_BY", &path)?,
+            rate_limit_cached_per_min: load_env("RATE_LIMIT_CACHED_PER_MIN", &path)?,
+            rate_limit_generated_per_min: load_env("RATE_LIMIT_GENERATED_PER_MIN", &path)?,
             speed_max_val: load_env("SPEED_MAX_VAL", &path)?,
             speed_min_val: load_env("SPEED_MIN_VAL", &path)?,
-            supported_langs: load_supported_langs(&path)?,
+            supported_langs,
             allowed_formats: load_allowed_formats(&path)?,
-            allowed_chars: load_allowed_chars(&path)?,
-            blacklisted_phrases: load_blacklisted_phrases(&path)?,
+            allowed_chars,
+            blacklist,
+            blacklist_policy: load_env("BLACKLIST_POLICY", &path)?,
+            users: load_users(&path)?,
+            issues,
         })
     }
 }
@@ -438,14 +708,46 @@ impl Config {
         &self.cache_path
     }
 
+    pub fn DATABASE_PATH(&self) -> &str {
+        &self.database_path
+    }
+
     pub fn MAX_CACHE_SIZE(&self) -> usize {
         self.max_cache_size
     }
 
+    pub fn CACHE_CONTROL_MAX_AGE(&self) -> u32 {
+        self.cache_control_max_age
+    }
+
+    pub fn SHARE_LINK_SECRET(&self) -> &str {
+        &self.share_link_secret
+    }
+
+    pub fn SHARE_LINK_TTL(&self) -> u32 {
+        self.share_link_ttl
+    }
+
     pub fn WORD_LENGTH_LIMIT(&self) -> usize {
         self.word_length_limit
     }
 
+    pub fn BATCH_SIZE_LIMIT(&self) -> usize {
+        self.batch_size_limit
+    }
+
+    pub fn RATE_LIMIT_BY(&self) -> RateLimitBy {
+        self.rate_limit_by
+    }
+
+    pub fn RATE_LIMIT_CACHED_PER_MIN(&self) -> u32 {
+        self.rate_limit_cached_per_min
+    }
+
+    pub fn RATE_LIMIT_GENERATED_PER_MIN(&self) -> u32 {
+        self.rate_limit_generated_per_min
+    }
+
     pub fn SPEED_MAX_VAL(&self) -> f32 {
         self.speed_max_val
     }
@@ -467,7 +769,23 @@ impl Config {
     }
 
     pub fn BLACKLISTED_PHRASES(&self) -> &[String] {
-        &self.blacklisted_phrases
+        self.blacklist.phrases()
+    }
+
+    pub fn BLACKLIST(&self) -> &Blacklist {
+        &self.blacklist
+    }
+
+    pub fn BLACKLIST_POLICY(&self) -> BlacklistPolicy {
+        self.blacklist_policy
+    }
+
+    pub fn USERS(&self) -> &HashMap<String, User> {
+        &self.users
+    }
+
+    pub fn ISSUES(&self) -> &[ConfigIssue] {
+        &self.issues
     }
 }
 
@@ -477,6 +795,22 @@ impl Config {
             Box::pin(async move {
                 //Generate Config
                 let config = Config::new(PathBuf::from("./config")).unwrap();
+
+                //Report any problems, refusing to launch if they are serious
+                let mut errors = 0;
+                for issue in config.ISSUES() {
+                    match issue.severity() {
+                        Severity::Warning => warn!("config: {}", issue),
+                        Severity::Error => {
+                            error!("config: {}", issue);
+                            errors += 1;
+                        }
+                    }
+                }
+                if errors > 0 {
+                    panic!("Configuration is not valid, found {} error(s)", errors);
+                }
+
                 //Save to State
                 rocket.manage(config)
             })
@@ -496,6 +830,50 @@ impl<'r> FromRequest<'r> for &'r Config {
     }
 }
 
+/// Authenticates a user from the api key provided in the `X-Api-Key` header, or the `api_key` query parameter.
+/// Fails with a 401 if the key is missing or unknown, and a 403 if the user has been disabled.
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for &'r User {
+    type Error = AuthError;
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, AuthError> {
+        let cfg = req
+            .rocket()
+            .state::<Config>()
+            .expect("Configuration Fairing Not Attached!");
+
+        let key = match api_key(req) {
+            Some(k) => k,
+            None => return request::Outcome::Failure((Status::Unauthorized, AuthError::Missing)),
+        };
+
+        match cfg.USERS().get(key) {
+            Some(user) if user.enabled => request::Outcome::Success(user),
+            Some(_) => request::Outcome::Failure((Status::Forbidden, AuthError::Disabled)),
+            None => request::Outcome::Failure((Status::Unauthorized, AuthError::Invalid)),
+        }
+    }
+}
+
+/// Authenticates an administrator, in the same way as a `User`.
+/// Fails with a 403 if the user is valid, but has not been granted access to the administrative endpoints.
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Admin<'r> {
+    type Error = AuthError;
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, AuthError> {
+        let user = match req.guard::<&User>().await {
+            request::Outcome::Success(u) => u,
+            request::Outcome::Failure(f) => return request::Outcome::Failure(f),
+            request::Outcome::Forward(f) => return request::Outcome::Forward(f),
+        };
+
+        if user.admin {
+            request::Outcome::Success(Admin(user))
+        } else {
+            request::Outcome::Failure((Status::Forbidden, AuthError::NotAdmin))
+        }
+    }
+}
+
 #[cfg(test)]
 #[cfg(not(tarpaulin_include))]
 mod tests {
@@ -503,7 +881,7 @@ mod tests {
 
     use crate::error::ConfigError;
 
-    use super::load_env;
+    use super::{load_env, RateLimitBy};
 
     #[test]
     #[should_panic]
@@ -511,4 +889,14 @@ mod tests {
         let _: Result<String, ConfigError> =
             load_env("this_value_does_not_exist123", &PathBuf::from("/"));
     }
+
+    #[test]
+    fn parse_rate_limit_by() {
+        assert_eq!("key".parse::<RateLimitBy>().unwrap(), RateLimitBy::Key);
+        assert_eq!("ip".parse::<RateLimitBy>().unwrap(), RateLimitBy::Ip);
+        match "user".parse::<RateLimitBy>() {
+            Err(ConfigError::UnknownRateLimitBy(s)) => assert_eq!(s, "user"),
+            r => panic!("expected an unknown rate limit key, got {:?}", r),
+        }
+    }
 }
@@ -1,12 +1,20 @@
 use std::{
     convert::Infallible,
     num::{ParseFloatError, ParseIntError},
+    path::PathBuf,
+    str::ParseBoolError,
 };
 
 #[derive(Debug)]
 pub enum ConfigError {
     ParseIntError(ParseIntError),
     ParseFloatError(ParseFloatError),
+    ParseBoolError(ParseBoolError),
+    UnknownBlacklistMode(String),
+    UnknownBlacklistPolicy(String),
+    UnknownRateLimitBy(String),
+    InvalidBlacklist(regex::Error),
+    UnreadableWordlist(PathBuf, std::io::Error),
 }
 
 impl std::fmt::Display for ConfigError {
@@ -14,6 +22,24 @@ impl std::fmt::Display for ConfigError {
         match *self {
             ConfigError::ParseIntError(_) => write!(f, "failed to parse int"),
             ConfigError::ParseFloatError(_) => write!(f, "failed to parse float"),
+            ConfigError::ParseBoolError(_) => write!(f, "failed to parse bool"),
+            ConfigError::UnknownBlacklistMode(ref m) => write!(
+                f,
+                "unknown blacklist mode ({}), expected substring, word or regex",
+                m
+            ),
+            ConfigError::UnknownBlacklistPolicy(ref p) => write!(
+                f,
+                "unknown blacklist policy ({}), expected reject, skip or bleep",
+                p
+            ),
+            ConfigError::UnknownRateLimitBy(ref k) => {
+                write!(f, "unknown rate limit key ({}), expected key or ip", k)
+            }
+            ConfigError::InvalidBlacklist(ref e) => write!(f, "invalid blacklist: {}", e),
+            ConfigError::UnreadableWordlist(ref p, ref e) => {
+                write!(f, "unable to read wordlist {}: {}", p.to_string_lossy(), e)
+            }
         }
     }
 }
@@ -23,6 +49,12 @@ impl std::error::Error for ConfigError {
         match *self {
             Self::ParseIntError(ref e) => Some(e),
             Self::ParseFloatError(ref e) => Some(e),
+            Self::ParseBoolError(ref e) => Some(e),
+            Self::UnknownBlacklistMode(_)
+            | Self::UnknownBlacklistPolicy(_)
+            | Self::UnknownRateLimitBy(_) => None,
+            Self::InvalidBlacklist(ref e) => Some(e),
+            Self::UnreadableWordlist(_, ref e) => Some(e),
         }
     }
 }
@@ -44,3 +76,37 @@ impl From<ParseFloatError> for ConfigError {
         Self::ParseFloatError(e)
     }
 }
+
+impl From<ParseBoolError> for ConfigError {
+    fn from(e: ParseBoolError) -> Self {
+        Self::ParseBoolError(e)
+    }
+}
+
+impl From<regex::Error> for ConfigError {
+    fn from(e: regex::Error) -> Self {
+        Self::InvalidBlacklist(e)
+    }
+}
+
+/// The reasons a request may fail to authenticate as a user.
+#[derive(Debug)]
+pub enum AuthError {
+    Missing,
+    Invalid,
+    Disabled,
+    NotAdmin,
+}
+
+impl std::fmt::Display for AuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            AuthError::Missing => write!(f, "no api key provided"),
+            AuthError::Invalid => write!(f, "api key is not valid"),
+            AuthError::Disabled => write!(f, "user is disabled"),
+            AuthError::NotAdmin => write!(f, "user is not an administrator"),
+        }
+    }
+}
+
+impl std::error::Error for AuthError {}
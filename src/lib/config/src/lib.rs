@@ -1,8 +1,13 @@
+mod blacklist;
 mod config;
 mod error;
-mod models;
+pub mod models;
+mod validation;
 
+pub use crate::blacklist::{Blacklist, BlacklistMode, BlacklistPolicy};
 pub use crate::config::*;
+pub use crate::error::AuthError;
+pub use crate::validation::{missing_voices, ConfigIssue, Severity};
 
 #[cfg(test)]
 mod tests {}
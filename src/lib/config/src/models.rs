@@ -1,9 +1,31 @@
+use rocket::serde::Serialize;
+
 /// Represents a possible language that the api may convert text into.
 /// This is loaded on boot from `./config/langs.toml`.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
+#[serde(crate = "rocket::serde")]
 pub struct Language {
     pub display_name: String,
     pub iso_691_code: String,
     pub festival_code: String,
     pub enabled: bool,
+    /// Wordlists of phrases which are blocked only for this language, relative to the configuration directory.
+    #[serde(skip)]
+    pub blacklist_files: Vec<String>,
+}
+
+/// Represents a user which may access the authenticated endpoints of the api.
+/// This is loaded on boot from `./config/users.toml`.
+#[derive(Debug, Clone)]
+pub struct User {
+    pub id: i32,
+    pub name: String,
+    pub key: String,
+    pub enabled: bool,
+    /// Whether this user may access the administrative endpoints of the api.
+    pub admin: bool,
 }
+
+/// A user who has been granted access to the administrative endpoints of the api.
+#[derive(Debug, Clone, Copy)]
+pub struct Admin<'a>(pub &'a User);
@@ -0,0 +1,226 @@
+//! Cross-validation of the configuration, catching mistakes which only show when the files are read together.
+//! Problems are reported as a `ConfigIssue`. Warnings are logged on startup, while errors prevent the api from launching.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+use crate::models::Language;
+
+/// How serious a problem with the configuration is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// The api can run, but may not behave as the configuration intends.
+    Warning,
+    /// The api must not be started with this configuration.
+    Error,
+}
+
+/// A problem found while validating the configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigIssue {
+    /// A blacklisted phrase contains a char which is not in `ALLOWED_CHARS`, so it could never be matched.
+    /// The phrase is ignored.
+    DisallowedBlacklistChar { phrase: String, c: char },
+    /// More than one language in `langs.toml` uses the same iso code, so only one of them may be used.
+    DuplicateIsoCode { code: String, langs: Vec<String> },
+    /// The voice file of an enabled language does not exist.
+    MissingVoice { lang: String, path: String },
+}
+
+impl ConfigIssue {
+    pub fn severity(&self) -> Severity {
+        match *self {
+            ConfigIssue::DisallowedBlacklistChar { .. } | ConfigIssue::MissingVoice { .. } => {
+                Severity::Warning
+            }
+            ConfigIssue::DuplicateIsoCode { .. } => Severity::Error,
+        }
+    }
+}
+
+impl std::fmt::Display for ConfigIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            ConfigIssue::DisallowedBlacklistChar { ref phrase, c } => write!(
+                f,
+                "blacklisted phrase ({}) contains a char ({}) which is not in ALLOWED_CHARS, it will be ignored",
+                phrase, c
+            ),
+            ConfigIssue::DuplicateIsoCode {
+                ref code,
+                ref langs,
+            } => write!(
+                f,
+                "iso code ({}) is used by more than one language ({})",
+                code,
+                langs.join(", ")
+            ),
+            ConfigIssue::MissingVoice { ref lang, ref path } => {
+                write!(f, "voice file ({}) of {} does not exist", path, lang)
+            }
+        }
+    }
+}
+
+/// Remove the phrases which contain a char that is not allowed, recording an issue for each.
+pub(crate) fn allowed_phrases(
+    phrases: Vec<String>,
+    allowed_chars: &HashSet<char>,
+    issues: &mut Vec<ConfigIssue>,
+) -> Vec<String> {
+    phrases
+        .into_iter()
+        .filter(
+            |phrase| match phrase.chars().find(|c| !allowed_chars.contains(c)) {
+                Some(c) => {
+                    issues.push(ConfigIssue::DisallowedBlacklistChar {
+                        phrase: phrase.clone(),
+                        c,
+                    });
+                    false
+                }
+                None => true,
+            },
+        )
+        .collect()
+}
+
+/// Key languages by their iso code, recording an issue for every code used by more than one language.
+pub(crate) fn unique_langs(
+    langs: Vec<Language>,
+    issues: &mut Vec<ConfigIssue>,
+) -> HashMap<String, Language> {
+    let mut names: HashMap<&str, Vec<String>> = HashMap::default();
+    for lang in langs.iter() {
+        names
+            .entry(&lang.iso_691_code)
+            .or_default()
+            .push(lang.display_name.clone());
+    }
+
+    let mut duplicates: Vec<ConfigIssue> = names
+        .into_iter()
+        .filter(|(_, langs)| langs.len() > 1)
+        .map(|(code, langs)| ConfigIssue::DuplicateIsoCode {
+            code: code.to_owned(),
+            langs,
+        })
+        .collect();
+    duplicates.sort_by_key(|i| i.to_string());
+    issues.extend(duplicates);
+
+    langs
+        .into_iter()
+        .map(|l| (l.iso_691_code.clone(), l))
+        .collect()
+}
+
+/// Find every enabled language whose voice file does not exist, sorted by display name.
+/// Voices which are not paths, such as those built in to flite, are not checked.
+pub fn missing_voices(langs: &HashMap<String, Language>) -> Vec<&Language> {
+    let mut langs: Vec<&Language> = langs
+        .values()
+        .filter(|l| l.enabled)
+        .filter(|l| {
+            let voice = &l.festival_code;
+            let is_path = voice.ends_with(".flitevox") || voice.contains(std::path::MAIN_SEPARATOR);
+            is_path && !Path::new(voice).exists()
+        })
+        .collect();
+    langs.sort_by(|a, b| a.display_name.cmp(&b.display_name));
+    langs
+}
+
+/// Record an issue for every enabled language whose voice file does not exist.
+pub(crate) fn check_voices(langs: &HashMap<String, Language>, issues: &mut Vec<ConfigIssue>) {
+    issues.extend(
+        missing_voices(langs)
+            .into_iter()
+            .map(|lang| ConfigIssue::MissingVoice {
+                lang: lang.display_name.clone(),
+                path: lang.festival_code.clone(),
+            }),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::{allowed_phrases, check_voices, unique_langs, ConfigIssue, Severity};
+    use crate::models::Language;
+
+    fn lang(name: &str, code: &str, voice: &str, enabled: bool) -> Language {
+        Language {
+            display_name: name.to_owned(),
+            iso_691_code: code.to_owned(),
+            festival_code: voice.to_owned(),
+            enabled,
+            blacklist_files: vec![],
+        }
+    }
+
+    #[test]
+    fn disallowed_blacklist_chars() {
+        let allowed: HashSet<char> = "abcdefghijklmnopqrstuvwxyz ".chars().collect();
+        let mut issues = vec![];
+        let phrases = vec![String::from(" test "), String::from("t3st")];
+
+        let phrases = allowed_phrases(phrases, &allowed, &mut issues);
+        assert_eq!(phrases, vec![String::from(" test ")]);
+        assert_eq!(
+            issues,
+            vec![ConfigIssue::DisallowedBlacklistChar {
+                phrase: String::from("t3st"),
+                c: '3'
+            }]
+        );
+        assert_eq!(issues[0].severity(), Severity::Warning);
+    }
+
+    #[test]
+    fn duplicate_iso_codes() {
+        let mut issues = vec![];
+        let langs = unique_langs(
+            vec![
+                lang("english", "en", "kal", true),
+                lang("maori", "mi", "kal", false),
+                lang("kiwi", "en", "kal", true),
+            ],
+            &mut issues,
+        );
+        assert_eq!(langs.len(), 2);
+        assert_eq!(
+            issues,
+            vec![ConfigIssue::DuplicateIsoCode {
+                code: String::from("en"),
+                langs: vec![String::from("english"), String::from("kiwi")]
+            }]
+        );
+        assert_eq!(issues[0].severity(), Severity::Error);
+    }
+
+    #[test]
+    fn missing_voices() {
+        let mut issues = vec![];
+        let langs = unique_langs(
+            vec![
+                lang("english", "en", "voices/does_not_exist.flitevox", true),
+                lang("maori", "mi", "voices/does_not_exist.flitevox", false),
+                lang("builtin", "bi", "kal", true),
+                lang("present", "pr", "Cargo.toml", true),
+            ],
+            &mut issues,
+        );
+        check_voices(&langs, &mut issues);
+        assert_eq!(
+            issues,
+            vec![ConfigIssue::MissingVoice {
+                lang: String::from("english"),
+                path: String::from("voices/does_not_exist.flitevox")
+            }]
+        );
+    }
+}
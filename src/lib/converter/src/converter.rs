@@ -1,7 +1,17 @@
 use async_trait::async_trait;
 use config::Config;
-use rocket::{error, fairing::AdHoc, request::FromRequest};
-use std::{collections::HashSet, convert::Infallible, path::PathBuf};
+use rocket::{
+    error,
+    fairing::AdHoc,
+    http::{Accept, MediaType},
+    request::FromRequest,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    convert::Infallible,
+    path::PathBuf,
+    sync::Arc,
+};
 use utils::phrase_package::PhrasePackage;
 
 #[derive(Debug)]
@@ -38,6 +48,8 @@ impl std::error::Error for ConversionError {
 pub trait ConverterSubprocess: Send + Sync + std::fmt::Debug {
     fn name(&self) -> &str;
     fn supported_outputs(&self) -> HashSet<String>;
+    /// The MIME type of a supported output format, returns None if the format is not supported.
+    fn mime_type(&self, format: &str) -> Option<&str>;
     async fn convert(
         &self,
         target_speed: f32,
@@ -47,9 +59,11 @@ pub trait ConverterSubprocess: Send + Sync + std::fmt::Debug {
     ) -> Result<PathBuf, ConversionError>;
 }
 
+#[derive(Debug, Clone)]
 pub struct Converter {
-    subs: Vec<Box<dyn ConverterSubprocess>>,
+    subs: Arc<Vec<Box<dyn ConverterSubprocess>>>,
     supported_types: HashSet<String>,
+    mime_types: Arc<HashMap<String, String>>,
 }
 
 impl Converter {
@@ -57,22 +71,100 @@ impl Converter {
         AdHoc::on_ignite("Tts Generator", |rocket| {
             Box::pin(async move {
                 let mut supported_types = HashSet::default();
+                let mut mime_types = HashMap::default();
                 for sub in &subs {
-                    supported_types.extend(sub.supported_outputs())
+                    for fmt in sub.supported_outputs() {
+                        //The first converter to support a format is the one used to produce it
+                        if let Some(mime) = sub.mime_type(&fmt) {
+                            mime_types
+                                .entry(fmt.clone())
+                                .or_insert_with(|| mime.to_owned());
+                        }
+                        supported_types.insert(fmt);
+                    }
                 }
 
                 rocket.manage(Converter {
-                    subs,
+                    subs: Arc::new(subs),
                     supported_types,
+                    mime_types: Arc::new(mime_types),
                 })
             })
         })
     }
 
-    pub fn is_supported(&self, to_check: &String) -> bool {
+    pub fn is_supported(&self, to_check: &str) -> bool {
         self.supported_types.contains(to_check)
     }
 
+    /// Returns the first converter which is able to output the provided format, this is the same
+    /// converter that will be attempted first by `convert()`.
+    pub fn handler(&self, format: &str) -> Option<&dyn ConverterSubprocess> {
+        self.subs
+            .iter()
+            .find(|sub| sub.supported_outputs().contains(format))
+            .map(|sub| sub.as_ref())
+    }
+
+    /// The MIME type of a supported output format, as reported by the converter which produces it.
+    pub fn mime_type(&self, format: &str) -> Option<&str> {
+        self.mime_types.get(format).map(|m| m.as_str())
+    }
+
+    /// Choose the format which best satisfies the media types a client accepts, from those which are both
+    /// allowed and supported. Media types are considered in order of their weight, with a specific type such as
+    /// `audio/mpeg` considered before a wildcard of equal weight, and wildcards such as `audio/*` preferring `wav`.
+    /// A client which does not state what it accepts will receive `wav` if allowed.
+    /// Returns None if none of the formats are acceptable to the client.
+    pub fn negotiate<'a>(
+        &self,
+        accept: Option<&Accept>,
+        allowed: &'a HashSet<String>,
+    ) -> Option<&'a str> {
+        let mut candidates: Vec<(&str, MediaType)> = allowed
+            .iter()
+            .filter_map(|fmt| {
+                let mime = MediaType::parse_flexible(self.mime_type(fmt)?)?;
+                Some((fmt.as_str(), mime))
+            })
+            .collect();
+        candidates.sort_by_key(|&(fmt, _)| (fmt != "wav", fmt));
+
+        let accept = match accept {
+            Some(a) => a,
+            None => return candidates.first().map(|&(fmt, _)| fmt),
+        };
+
+        let matches = |range: &MediaType, mime: &MediaType| {
+            (range.top() == "*" || range.top() == mime.top())
+                && (range.sub() == "*" || range.sub() == mime.sub())
+        };
+
+        //Media types given a weight of 0 are explicitly refused by the client
+        candidates.retain(|(_, mime)| {
+            !accept.iter().any(|q| {
+                q.weight_or(1.0) <= 0.0
+                    && q.media_type().top() == mime.top()
+                    && q.media_type().sub() == mime.sub()
+            })
+        });
+
+        let mut ranges: Vec<_> = accept.iter().filter(|q| q.weight_or(1.0) > 0.0).collect();
+        let specificity =
+            |range: &MediaType| (range.top() != "*") as u8 + (range.sub() != "*") as u8;
+        ranges.sort_by(|a, b| {
+            b.weight_or(1.0)
+                .total_cmp(&a.weight_or(1.0))
+                .then_with(|| specificity(b.media_type()).cmp(&specificity(a.media_type())))
+        });
+        ranges.iter().find_map(|q| {
+            candidates
+                .iter()
+                .find(|(_, mime)| matches(q.media_type(), mime))
+                .map(|&(fmt, _)| fmt)
+        })
+    }
+
     //XXX improve error responses
     pub async fn convert(
         &self,
@@ -81,9 +173,9 @@ impl Converter {
         cfg: &Config,
     ) -> Result<PathBuf, ()> {
         for sub in self.subs.iter() {
-            if sub.supported_outputs().contains(&phrase_package.fmt) {
+            if sub.supported_outputs().contains(phrase_package.format()) {
                 match sub
-                    .convert(target_speed, phrase_package, &phrase_package.fmt, cfg)
+                    .convert(target_speed, phrase_package, phrase_package.format(), cfg)
                     .await
                 {
                     Ok(res) => return Ok(res),
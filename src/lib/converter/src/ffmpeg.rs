@@ -1,10 +1,11 @@
 //! A wrapper for ffmpeg, a library for converting from one audio format to another (among other things).
 
-use std::{collections::HashSet, path::PathBuf, process::Command};
+use std::{collections::HashSet, path::PathBuf};
 
 use crate::{ConversionError, ConverterSubprocess};
 use async_trait::async_trait;
 use config::Config;
+use tokio::process::Command;
 use utils::phrase_package::PhrasePackage;
 
 #[derive(Debug)]
@@ -17,7 +18,7 @@ impl Ffmpeg {
     /// event of a failure.
     pub fn new() -> Result<Self, String> {
         //Check to see if ffmpeg is installed
-        let con = Command::new("ffmpeg")
+        let con = std::process::Command::new("ffmpeg")
             .arg("-version")
             .output()
             .map_err(|_| String::from("ffmpeg not installed"))?;
@@ -47,6 +48,19 @@ impl ConverterSubprocess for Ffmpeg {
         ])
     }
 
+    fn mime_type(&self, format: &str) -> Option<&str> {
+        match format {
+            "mp3" => Some("audio/mpeg"),
+            "wav" => Some("audio/wav"),
+            "flac" => Some("audio/flac"),
+            "m4a" => Some("audio/mp4"),
+            "wma" => Some("audio/x-ms-wma"),
+            "aac" => Some("audio/aac"),
+            "aif" => Some("audio/aiff"),
+            _ => None,
+        }
+    }
+
     async fn convert(
         &self,
         desired_speed: f32,
@@ -90,7 +104,8 @@ impl ConverterSubprocess for Ffmpeg {
             .arg(format!("atempo={}", desired_speed)) //Change speed of audio
             .arg("-vn") //Strip & disable all video
             .arg(&converted_file_path)
-            .output();
+            .output()
+            .await;
 
         match con {
             Ok(o) if o.status.success() => Ok(converted_file_path),
@@ -1,9 +1,13 @@
-use std::{convert::Infallible, path::PathBuf, process::Command};
+use std::{
+    convert::Infallible,
+    path::{Path, PathBuf},
+};
 
 use rocket::request::FromRequest;
-use utils::phrase_package::PhrasePackage;
+use tokio::process::Command;
+use utils::{generate_random_alphanumeric, phrase_package::PhrasePackage};
 
-use crate::TtsGenerator;
+use crate::{wav::Wav, TtsGenerator};
 
 #[derive(Debug)]
 pub enum FliteError {
@@ -28,8 +32,126 @@ impl std::error::Error for FliteError {
     }
 }
 
+#[derive(Debug, Clone)]
 pub struct Flite {}
 
+impl Flite {
+    /// Read the provided text with a voice, saving it as a wav file at the provided path.
+    async fn synthesize(
+        &self,
+        voice: &str,
+        text: &str,
+        file_path: &Path,
+    ) -> Result<(), FliteError> {
+        let word_gen = Command::new("flite")
+            .arg("-voice")
+            .arg(voice)
+            .arg("-t")
+            .arg(format!("\"{}\"", text))
+            .arg("-o")
+            .arg(file_path)
+            .spawn();
+
+        let word_gen = match word_gen {
+            Ok(f) => f.wait_with_output().await,
+            Err(e) => return Err(FliteError::UnableToStart(e)),
+        };
+
+        match word_gen {
+            Ok(f) if f.status.success() => {}
+            Ok(f) => {
+                let stdout = String::from_utf8(f.stdout)
+                    .unwrap_or_else(|_| "Unable to parse stdout!".into());
+                let stderr = String::from_utf8(f.stderr)
+                    .unwrap_or_else(|_| "Unable to parse stderr!".into());
+
+                return Err(
+                    FliteError::ProcessError(
+                        format!("Failed to generate wav from provided string due to error.\nStdout: \n{}\nStderr: \n{}", stdout, stderr)
+                    )
+                );
+            }
+            Err(e) => return Err(FliteError::IoFailure(e)),
+        }
+
+        Ok(())
+    }
+
+    /// Read a phrase in parts, replacing each of its bleeps with a tone as long as the bleeped words take to read.
+    async fn synthesize_bleeped(
+        &self,
+        voice: &str,
+        details: &PhrasePackage,
+    ) -> Result<Wav, FliteError> {
+        //Split the phrase around its bleeps, skipping anything between them which has nothing to read
+        let mut parts: Vec<(&str, bool)> = vec![];
+        let mut last = 0;
+        for bleep in details.bleeps.iter() {
+            parts.push((&details.word[last..bleep.start], false));
+            parts.push((&details.word[bleep.clone()], true));
+            last = bleep.end;
+        }
+        parts.push((&details.word[last..], false));
+        parts.retain(|(text, _)| text.chars().any(char::is_alphanumeric));
+
+        let mut wav: Option<Wav> = None;
+        for (text, bleep) in parts {
+            let part_path = std::env::temp_dir().join(format!(
+                "festival-part-{}.wav",
+                generate_random_alphanumeric(16)
+            ));
+            let result = self.synthesize(voice, text, &part_path).await;
+            let bytes = match result {
+                Ok(_) => tokio::fs::read(&part_path)
+                    .await
+                    .map_err(FliteError::IoFailure),
+                Err(e) => Err(e),
+            };
+            let _ = tokio::fs::remove_file(&part_path).await;
+
+            let mut part = Wav::parse(&bytes?).ok_or_else(|| {
+                FliteError::ProcessError(String::from("Flite produced an invalid wav file"))
+            })?;
+            if bleep {
+                part.bleep();
+            }
+            match wav {
+                Some(ref mut wav) => {
+                    if !wav.append(part) {
+                        return Err(FliteError::ProcessError(String::from(
+                            "Flite produced wav files of differing formats",
+                        )));
+                    }
+                }
+                None => wav = Some(part),
+            }
+        }
+
+        wav.ok_or_else(|| FliteError::ProcessError(String::from("Nothing to read in phrase")))
+    }
+
+    /// Check that the flite binary is installed and runs. Note that some builds of flite exit with
+    /// a failure status after printing their version, so the output is checked instead.
+    pub async fn check(&self) -> Result<(), FliteError> {
+        let output = Command::new("flite")
+            .arg("--version")
+            .output()
+            .await
+            .map_err(FliteError::UnableToStart)?;
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        if output.status.success() || stdout.contains("flite") {
+            return Ok(());
+        }
+
+        Err(FliteError::ProcessError(format!(
+            "Failed to run flite.\nStdout: \n{}\nStderr: \n{}",
+            stdout,
+            String::from_utf8_lossy(&output.stderr)
+        )))
+    }
+}
+
 #[rocket::async_trait]
 impl<'r> FromRequest<'r> for &'r Flite {
     type Error = Infallible;
@@ -64,41 +186,19 @@ impl<'r> TtsGenerator<'r> for Flite {
             return Ok(file_path);
         }
 
-        let word_gen = Command::new("flite")
-            .arg("-voice")
-            .arg(
-                &config
-                    .SUPPORTED_LANGS()
-                    .get(&details.lang)
-                    .unwrap()
-                    .festival_code,
-            )
-            .arg("-t")
-            .arg(format!("\"{}\"", &details.word))
-            .arg("-o")
-            .arg(&file_path)
-            .spawn();
-
-        let word_gen = match word_gen {
-            Ok(f) => f.wait_with_output(),
-            Err(e) => return Err(FliteError::UnableToStart(e)),
-        };
-
-        match word_gen {
-            Ok(f) if f.status.success() => {}
-            Ok(f) => {
-                let stdout = String::from_utf8(f.stdout)
-                    .unwrap_or_else(|_| "Unable to parse stdout!".into());
-                let stderr = String::from_utf8(f.stderr)
-                    .unwrap_or_else(|_| "Unable to parse stderr!".into());
-
-                return Err(
-                    FliteError::ProcessError(
-                        format!("Failed to generate wav from provided string due to error.\nStdout: \n{}\nStderr: \n{}", stdout, stderr)
-                    )
-                );
-            }
-            Err(e) => return Err(FliteError::IoFailure(e)),
+        let voice = &config
+            .SUPPORTED_LANGS()
+            .get(&details.lang)
+            .unwrap()
+            .festival_code;
+
+        if details.bleeps.is_empty() {
+            self.synthesize(voice, &details.word, &file_path).await?;
+        } else {
+            let wav = self.synthesize_bleeped(voice, details).await?;
+            tokio::fs::write(&file_path, wav.to_bytes())
+                .await
+                .map_err(FliteError::IoFailure)?;
         }
 
         Ok(file_path)
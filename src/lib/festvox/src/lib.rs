@@ -1,5 +1,6 @@
 mod flite;
 mod tts;
+mod wav;
 
 pub use flite::*;
 pub use tts::TtsGenerator;
@@ -0,0 +1,159 @@
+//! Minimal handling of the PCM wav files produced by flite, allowing phrases generated in parts to be joined,
+//! and parts of a phrase to be replaced by a tone.
+
+/// The frequency of the tone which replaces bleeped words, in hertz.
+const BLEEP_FREQUENCY: f64 = 1000.0;
+
+/// The amplitude of the tone which replaces bleeped words, as a fraction of the loudest possible sample.
+const BLEEP_AMPLITUDE: f64 = 0.3;
+
+/// A PCM wav file, split into its format and samples.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Wav {
+    /// The body of the `fmt ` chunk, describing the samples.
+    format: Vec<u8>,
+    /// The body of the `data` chunk.
+    data: Vec<u8>,
+}
+
+impl Wav {
+    /// Parse a wav file, returning None if it is not a wav file or is missing the format or data.
+    pub fn parse(bytes: &[u8]) -> Option<Wav> {
+        if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+            return None;
+        }
+
+        let mut format = None;
+        let mut data = None;
+        let mut rest = &bytes[12..];
+        while rest.len() >= 8 {
+            let id = &rest[0..4];
+            let size = u32::from_le_bytes([rest[4], rest[5], rest[6], rest[7]]) as usize;
+            //Some writers leave the size of the data chunk as a placeholder, so it is clamped to what is present
+            let body = &rest[8..(8 + size).min(rest.len())];
+            match id {
+                b"fmt " => format = Some(body.to_vec()),
+                b"data" => data = Some(body.to_vec()),
+                _ => {}
+            }
+            //Chunks are padded to an even length
+            rest = &rest[(8 + size + size % 2).min(rest.len())..];
+        }
+
+        let format = format.filter(|f| f.len() >= 16)?;
+        Some(Wav {
+            format,
+            data: data?,
+        })
+    }
+
+    fn channels(&self) -> u16 {
+        u16::from_le_bytes([self.format[2], self.format[3]])
+    }
+
+    fn sample_rate(&self) -> u32 {
+        u32::from_le_bytes([
+            self.format[4],
+            self.format[5],
+            self.format[6],
+            self.format[7],
+        ])
+    }
+
+    fn bits_per_sample(&self) -> u16 {
+        u16::from_le_bytes([self.format[14], self.format[15]])
+    }
+
+    /// Replace the audio with a tone of the same duration. Flite only produces 16 bit samples, so the tone is only
+    /// written in that format, other formats are silenced instead rather than risk writing noise.
+    pub fn bleep(&mut self) {
+        let channels = self.channels().max(1) as usize;
+        if self.bits_per_sample() != 16 {
+            self.data.iter_mut().for_each(|b| *b = 0);
+            return;
+        }
+
+        let step = 2.0 * std::f64::consts::PI * BLEEP_FREQUENCY / self.sample_rate().max(1) as f64;
+        let amplitude = BLEEP_AMPLITUDE * i16::MAX as f64;
+        for (i, frame) in self.data.chunks_exact_mut(2 * channels).enumerate() {
+            let sample = ((i as f64 * step).sin() * amplitude) as i16;
+            for channel in frame.chunks_exact_mut(2) {
+                channel.copy_from_slice(&sample.to_le_bytes());
+            }
+        }
+    }
+
+    /// Append the audio of another wav file to this one, returning false if their formats differ.
+    pub fn append(&mut self, other: Wav) -> bool {
+        if self.format != other.format {
+            return false;
+        }
+        self.data.extend(other.data);
+        true
+    }
+
+    /// Encode this as a wav file.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(28 + self.format.len() + self.data.len());
+        let riff_size = 4 + 8 + self.format.len() + 8 + self.data.len() + self.data.len() % 2;
+        bytes.extend_from_slice(b"RIFF");
+        bytes.extend_from_slice(&(riff_size as u32).to_le_bytes());
+        bytes.extend_from_slice(b"WAVE");
+        bytes.extend_from_slice(b"fmt ");
+        bytes.extend_from_slice(&(self.format.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.format);
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&(self.data.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.data);
+        if self.data.len() % 2 == 1 {
+            bytes.push(0);
+        }
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Wav;
+
+    /// A 16 bit mono wav at 16khz, holding the provided samples.
+    fn wav(samples: &[i16]) -> Wav {
+        let mut format = vec![];
+        format.extend_from_slice(&1u16.to_le_bytes());
+        format.extend_from_slice(&1u16.to_le_bytes());
+        format.extend_from_slice(&16000u32.to_le_bytes());
+        format.extend_from_slice(&32000u32.to_le_bytes());
+        format.extend_from_slice(&2u16.to_le_bytes());
+        format.extend_from_slice(&16u16.to_le_bytes());
+        Wav {
+            format,
+            data: samples.iter().flat_map(|s| s.to_le_bytes()).collect(),
+        }
+    }
+
+    #[test]
+    fn parse_and_encode() {
+        let original = wav(&[0, 1, -1, 300]);
+        let bytes = original.to_bytes();
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(Wav::parse(&bytes), Some(original));
+
+        assert_eq!(Wav::parse(b"not a wav file"), None);
+    }
+
+    #[test]
+    fn bleep_and_append() {
+        let mut bleeped = wav(&[0; 64]);
+        bleeped.bleep();
+        assert_eq!(bleeped.data.len(), 128);
+        assert!(bleeped.data.iter().any(|b| *b != 0));
+
+        let mut joined = wav(&[1, 2]);
+        assert!(joined.append(bleeped));
+        assert_eq!(joined.data.len(), 132);
+
+        let mut other = wav(&[1]);
+        other.format[4] = 0;
+        assert!(!joined.append(other));
+    }
+}
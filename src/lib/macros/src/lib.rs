@@ -4,13 +4,13 @@
 
 /// A macro to shorthand the rejection from an endpoint due to a bad request.
 /// Should be used when you want a quick 400 response to the user.
-/// Note that a string must be provided (or used like `format!()`).
+/// A stable error code must be provided as an identifier, followed by a message (which may be used like `format!()`).
 ///
 /// **Examples**
 /// ```ignore
 ///     #[get("/")]
 ///     fn index() -> Response {
-///         reject!("You're not cool enough to use this api!");
+///         reject!(not_cool, "You're not cool enough to use this api!");
 ///     }
 /// ```
 ///
@@ -18,49 +18,55 @@
 ///     #[get("/")]
 ///     fn index() -> Response {
 ///         let reason: String = "tall".to_string();
-///         reject!("You're not {} enough to use this api!", reason);
+///         reject!(not_tall, "You're not {} enough to use this api!", reason);
 ///     }
 /// ```
 ///
-/// If you need more detail in your rejection, you should construct a Response
-/// manually for the user. This returns with `ContentType: Plain/Text;`.
+/// If you need more detail in your rejection, such as the field which caused it, you should construct a
+/// `Problem` manually for the user. This returns with `ContentType: application/problem+json;`.
 #[macro_export]
 macro_rules! reject {
     () => {
+        compile_error!("An error code and string must be provided to rejection macro!");
+    };
+    ($code:ident) => {
         compile_error!("String must be provided to rejection macro!");
     };
-    ($arg:tt) => {
+    ($code:ident, $arg:tt) => {
         {
-            use response::{Data, Response};
+            use response::{Problem, Response};
             use rocket::http::Status;
 
-            return Err(Response::TextErr(Data {
-                data: String::from($arg),
-                status: Status::BadRequest,
-            }));
+            return Err(Response::Problem(Problem::new(
+                Status::BadRequest,
+                stringify!($code),
+                String::from($arg),
+            )));
         }
     };
-    ($($arg:tt)*) => {
+    ($code:ident, $($arg:tt)*) => {
         {
-            use response::{Data, Response};
+            use response::{Problem, Response};
             use rocket::http::Status;
-            return Err(Response::TextErr(Data {
-                data: format!($($arg)*),
-                status: Status::BadRequest,
-            }));
+
+            return Err(Response::Problem(Problem::new(
+                Status::BadRequest,
+                stringify!($code),
+                format!($($arg)*),
+            )));
         }
     };
 }
 
 /// A macro to shorthand the rejection from an endpoint due to a server error.
-/// /// Should be used when you want a quick 500 response to the user.
-/// Note that a string must be provided (or used like `format!()`).
+/// Should be used when you want a quick 500 response to the user.
+/// A stable error code must be provided as an identifier, followed by a message (which may be used like `format!()`).
 ///
 /// **Examples**
 /// ```ignore
 ///     #[get("/")]
 ///     fn index() -> Response {
-///         failure!("The server had a critical error processing your request!");
+///         failure!(critical_error, "The server had a critical error processing your request!");
 ///     }
 /// ```
 ///
@@ -68,33 +74,42 @@ macro_rules! reject {
 ///     #[get("/")]
 ///     fn index() -> Response {
 ///         let reason: String = "it caught fire!".to_string();
-///         failure!("The server failed to process your request becuase {}", reason);
+///         failure!(on_fire, "The server failed to process your request becuase {}", reason);
 ///     }
 /// ```
 ///
 /// If you need a more detailed failure response other than 500 + a message
-/// please construct the response manually.
+/// please construct the `Problem` manually.
 #[macro_export]
 macro_rules! failure {
     () => {
+        compile_error!("An error code and string must be provided to error macro!");
+    };
+    ($code:ident) => {
         compile_error!("String must be provided to error macro!");
     };
-    ($arg:tt) => {
+    ($code:ident, $arg:tt) => {
         {
-            use response::{Data, Response};
-            return Err(Response::TextErr(Data {
-                data: String::from($arg),
-                status: Status::InternalServerError,
-            }));
+            use response::{Problem, Response};
+            use rocket::http::Status;
+
+            return Err(Response::Problem(Problem::new(
+                Status::InternalServerError,
+                stringify!($code),
+                String::from($arg),
+            )));
         }
     };
-    ($($arg:tt)*) => {
+    ($code:ident, $($arg:tt)*) => {
         {
-            use response::{Data, Response};
-            return Err(Response::TextErr(Data {
-                data: format!($($arg)*),
-                status: Status::InternalServerError,
-            }));
+            use response::{Problem, Response};
+            use rocket::http::Status;
+
+            return Err(Response::Problem(Problem::new(
+                Status::InternalServerError,
+                stringify!($code),
+                format!($($arg)*),
+            )));
         }
     };
 }
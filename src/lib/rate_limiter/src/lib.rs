@@ -0,0 +1,7 @@
+//! A token bucket rate limiter for the api, used to prevent a single client from monopolising the
+//! resources of the api. Requests which are served from the cache and requests which must be generated
+//! are given separate budgets, as only generation is expensive.
+
+mod rate_limiter;
+
+pub use crate::rate_limiter::*;
@@ -0,0 +1,344 @@
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use config::{Config, RateLimitBy};
+use rocket::{
+    fairing::AdHoc,
+    request::{self, FromRequest},
+    Request,
+};
+
+/// The number of clients that may be tracked before idle clients are forgotten.
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// A single token bucket, which refills continuously up to its capacity.
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(capacity: u32) -> Self {
+        TokenBucket {
+            tokens: capacity as f64,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Refill the bucket based on the time elapsed since it was last refilled.
+    fn refill(&mut self, capacity: u32, now: Instant) {
+        let per_sec = capacity as f64 / 60.0;
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * per_sec).min(capacity as f64);
+        self.last_refill = now;
+    }
+
+    /// How long until the bucket contains the requested number of tokens, None if it already does.
+    fn wait_for(&self, capacity: u32, cost: u32) -> Option<Duration> {
+        if capacity == 0 || self.tokens >= cost as f64 {
+            return None;
+        }
+        if cost > capacity {
+            return Some(Duration::from_secs(60));
+        }
+        let per_sec = capacity as f64 / 60.0;
+        Some(Duration::from_secs_f64(
+            (cost as f64 - self.tokens) / per_sec,
+        ))
+    }
+
+    /// Spend as many of the requested tokens as the bucket contains, returning the number spent.
+    fn take_up_to(&mut self, capacity: u32, cost: u32) -> u32 {
+        if capacity == 0 {
+            return cost;
+        }
+        let taken = (self.tokens.max(0.0).floor() as u32).min(cost);
+        self.tokens -= taken as f64;
+        taken
+    }
+}
+
+/// The number of requests from each budget that a client was permitted to make, see `RateLimiter::acquire_up_to`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Grant {
+    pub cached: u32,
+    pub generated: u32,
+}
+
+/// The budgets of a single client.
+#[derive(Debug)]
+struct ClientBuckets {
+    cached: TokenBucket,
+    generated: TokenBucket,
+}
+
+/// Limits the number of requests each client may make to the api, with separate budgets for requests
+/// that are served from the cache and those that must be generated.
+#[derive(Debug)]
+pub struct RateLimiter {
+    limit_by: RateLimitBy,
+    cached_per_min: u32,
+    generated_per_min: u32,
+    clients: Mutex<HashMap<String, ClientBuckets>>,
+}
+
+impl RateLimiter {
+    /// Create a new rate limiter, a limit of 0 disables limiting for that budget.
+    pub fn new(limit_by: RateLimitBy, cached_per_min: u32, generated_per_min: u32) -> Self {
+        RateLimiter {
+            limit_by,
+            cached_per_min,
+            generated_per_min,
+            clients: Mutex::new(HashMap::default()),
+        }
+    }
+
+    pub fn fairing() -> AdHoc {
+        AdHoc::on_ignite("Rate Limiter", |rocket| {
+            Box::pin(async move {
+                let cfg = rocket
+                    .state::<Config>()
+                    .expect("Configuration Fairing Not Attached!");
+
+                let limiter = RateLimiter::new(
+                    cfg.RATE_LIMIT_BY(),
+                    cfg.RATE_LIMIT_CACHED_PER_MIN(),
+                    cfg.RATE_LIMIT_GENERATED_PER_MIN(),
+                );
+
+                rocket.manage(limiter)
+            })
+        })
+    }
+
+    /// Find the budgets of a client, refilled to the current time.
+    fn buckets<'a>(
+        &self,
+        clients: &'a mut HashMap<String, ClientBuckets>,
+        client: &ClientId,
+    ) -> &'a mut ClientBuckets {
+        let now = Instant::now();
+        if clients.len() >= MAX_TRACKED_CLIENTS && !clients.contains_key(&client.0) {
+            self.forget_idle(clients, now);
+        }
+
+        let buckets = clients
+            .entry(client.0.clone())
+            .or_insert_with(|| ClientBuckets {
+                cached: TokenBucket::new(self.cached_per_min),
+                generated: TokenBucket::new(self.generated_per_min),
+            });
+        buckets.cached.refill(self.cached_per_min, now);
+        buckets.generated.refill(self.generated_per_min, now);
+        buckets
+    }
+
+    /// Attempt to spend tokens from a client's budgets, either both budgets are charged or neither is.
+    /// If the client does not have enough tokens remaining, returns how long they must wait before retrying.
+    pub fn acquire(&self, client: &ClientId, cached: u32, generated: u32) -> Result<(), Duration> {
+        let mut clients = self.clients.lock().expect("rate limiter lock poisoned");
+        let buckets = self.buckets(&mut clients, client);
+
+        let wait = [
+            buckets.cached.wait_for(self.cached_per_min, cached),
+            buckets
+                .generated
+                .wait_for(self.generated_per_min, generated),
+        ]
+        .iter()
+        .flatten()
+        .max()
+        .copied();
+        if let Some(wait) = wait {
+            return Err(wait);
+        }
+
+        if self.cached_per_min != 0 {
+            buckets.cached.tokens -= cached as f64;
+        }
+        if self.generated_per_min != 0 {
+            buckets.generated.tokens -= generated as f64;
+        }
+        Ok(())
+    }
+
+    /// Spend as many tokens as a client's budgets allow, up to the number requested from each, returning
+    /// how many were spent. Used for batches, which may request more than a budget can ever hold, so that
+    /// the requests which do fit are still made.
+    /// If no tokens could be spent at all, returns how long the client must wait before retrying.
+    pub fn acquire_up_to(
+        &self,
+        client: &ClientId,
+        cached: u32,
+        generated: u32,
+    ) -> Result<Grant, Duration> {
+        let mut clients = self.clients.lock().expect("rate limiter lock poisoned");
+        let buckets = self.buckets(&mut clients, client);
+
+        let grant = Grant {
+            cached: buckets.cached.take_up_to(self.cached_per_min, cached),
+            generated: buckets
+                .generated
+                .take_up_to(self.generated_per_min, generated),
+        };
+        if cached + generated == 0 || grant.cached + grant.generated > 0 {
+            return Ok(grant);
+        }
+
+        let wait = [
+            (cached > 0)
+                .then(|| buckets.cached.wait_for(self.cached_per_min, 1))
+                .flatten(),
+            (generated > 0)
+                .then(|| buckets.generated.wait_for(self.generated_per_min, 1))
+                .flatten(),
+        ]
+        .iter()
+        .flatten()
+        .min()
+        .copied();
+        Err(wait.unwrap_or_default())
+    }
+
+    /// Forget any clients whose budgets have completely refilled, as they are indistinguishable from new clients.
+    fn forget_idle(&self, clients: &mut HashMap<String, ClientBuckets>, now: Instant) {
+        clients.retain(|_, b| {
+            b.cached.refill(self.cached_per_min, now);
+            b.generated.refill(self.generated_per_min, now);
+            b.cached.tokens < self.cached_per_min as f64
+                || b.generated.tokens < self.generated_per_min as f64
+        });
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for &'r RateLimiter {
+    type Error = Infallible;
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Infallible> {
+        let state = req
+            .rocket()
+            .state::<RateLimiter>()
+            .expect("rate limiter fairing attached");
+        request::Outcome::Success(state)
+    }
+}
+
+/// Identifies the client making a request, for the purposes of rate limiting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientId(pub String);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ClientId {
+    type Error = Infallible;
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Infallible> {
+        let limiter = req
+            .rocket()
+            .state::<RateLimiter>()
+            .expect("rate limiter fairing attached");
+
+        let ip = req
+            .client_ip()
+            .map(|ip| ip.to_string())
+            .unwrap_or_else(|| String::from("unknown"));
+
+        let id = match (limiter.limit_by, config::api_key(req)) {
+            (RateLimitBy::Key, Some(key)) => format!("key:{}", key),
+            _ => format!("ip:{}", ip),
+        };
+
+        request::Outcome::Success(ClientId(id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{ClientId, Grant, RateLimiter};
+    use config::RateLimitBy;
+
+    #[test]
+    fn separate_budgets() {
+        let limiter = RateLimiter::new(RateLimitBy::Key, 3, 1);
+        let client = ClientId(String::from("key:test"));
+
+        limiter.acquire(&client, 0, 1).expect("a generation token");
+        limiter
+            .acquire(&client, 0, 1)
+            .expect_err("generation budget should be spent");
+
+        //Cached requests have their own budget
+        for _ in 0..3 {
+            limiter.acquire(&client, 1, 0).expect("a cached token");
+        }
+        let wait = limiter
+            .acquire(&client, 1, 0)
+            .expect_err("cached budget should be spent");
+        assert!(wait <= Duration::from_secs(20));
+
+        //Other clients are unaffected
+        let other = ClientId(String::from("key:other"));
+        limiter.acquire(&other, 3, 1).expect("a fresh budget");
+    }
+
+    #[test]
+    fn all_or_nothing() {
+        let limiter = RateLimiter::new(RateLimitBy::Ip, 2, 1);
+        let client = ClientId(String::from("ip:127.0.0.1"));
+
+        //Not enough generation tokens, so no cached tokens should be spent either
+        limiter
+            .acquire(&client, 2, 2)
+            .expect_err("not enough generation tokens");
+        limiter.acquire(&client, 2, 1).expect("an untouched budget");
+    }
+
+    #[test]
+    fn partial_grants() {
+        let limiter = RateLimiter::new(RateLimitBy::Key, 10, 20);
+        let client = ClientId(String::from("key:test"));
+
+        //More than a budget could ever hold is partially granted, rather than refused forever
+        let grant = limiter
+            .acquire_up_to(&client, 2, 21)
+            .expect("a partial grant");
+        assert_eq!(
+            grant,
+            Grant {
+                cached: 2,
+                generated: 20
+            }
+        );
+
+        //Cached tokens remain, so those are still granted
+        let grant = limiter
+            .acquire_up_to(&client, 1, 1)
+            .expect("a partial grant");
+        assert_eq!(
+            grant,
+            Grant {
+                cached: 1,
+                generated: 0
+            }
+        );
+
+        let wait = limiter
+            .acquire_up_to(&client, 0, 1)
+            .expect_err("generation budget should be spent");
+        assert!(wait > Duration::from_secs(0) && wait <= Duration::from_secs(3));
+    }
+
+    #[test]
+    fn disabled_limits() {
+        let limiter = RateLimiter::new(RateLimitBy::Key, 0, 0);
+        let client = ClientId(String::from("key:test"));
+        for _ in 0..1000 {
+            limiter.acquire(&client, 1, 1).expect("unlimited budget");
+        }
+    }
+}
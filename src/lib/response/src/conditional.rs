@@ -0,0 +1,116 @@
+//! Conditional and partial requests for files, allowing clients to revalidate their caches and seek
+//! through or resume downloads.
+
+use std::convert::Infallible;
+
+use rocket::{
+    request::{self, FromRequest},
+    Request,
+};
+
+/// The headers of a request which control whether a file is sent in full, in part, or not at all.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FileRequest<'r> {
+    pub if_none_match: Option<&'r str>,
+    pub if_range: Option<&'r str>,
+    pub range: Option<&'r str>,
+}
+
+impl<'r> FileRequest<'r> {
+    /// Whether the client already holds the file with the provided etag, using the weak comparison
+    /// required for `If-None-Match`.
+    pub fn not_modified(&self, etag: &str) -> bool {
+        let if_none_match = match self.if_none_match {
+            Some(h) => h,
+            None => return false,
+        };
+        if if_none_match.trim() == "*" {
+            return true;
+        }
+        let etag = etag.trim_start_matches("W/");
+        if_none_match
+            .split(',')
+            .any(|tag| tag.trim().trim_start_matches("W/") == etag)
+    }
+
+    /// The range requested by the client, if any. A range is ignored if it is conditional on an
+    /// `If-Range` which does not match the current etag, in which case the full file should be sent.
+    pub fn range(&self, etag: &str) -> Option<&'r str> {
+        match self.if_range {
+            Some(tag) if tag.trim() != etag => None,
+            _ => self.range,
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for FileRequest<'r> {
+    type Error = Infallible;
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Infallible> {
+        let headers = req.headers();
+        request::Outcome::Success(FileRequest {
+            if_none_match: headers.get_one("If-None-Match"),
+            if_range: headers.get_one("If-Range"),
+            range: headers.get_one("Range"),
+        })
+    }
+}
+
+/// A single range of bytes within a file, both ends inclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+    /// The total size of the file this range is taken from.
+    pub total: u64,
+}
+
+/// The range requested lies entirely outside of the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnsatisfiableRange;
+
+impl ByteRange {
+    /// Parse the value of a `Range` header against a file of the provided size.
+    /// Headers which are malformed, or request multiple ranges, are ignored and return None, in which
+    /// case the full file should be sent.
+    pub fn parse(header: &str, total: u64) -> Result<Option<ByteRange>, UnsatisfiableRange> {
+        let spec = match header.trim().strip_prefix("bytes=") {
+            Some(s) if !s.contains(',') => s.trim(),
+            _ => return Ok(None),
+        };
+        let (start, end) = match spec.split_once('-') {
+            Some(s) => s,
+            None => return Ok(None),
+        };
+
+        let (start, end) = match (start.parse::<u64>(), end) {
+            //A suffix range, the last n bytes of the file
+            (Err(_), end) if start.is_empty() => {
+                let n = match end.parse::<u64>() {
+                    Ok(n) => n,
+                    Err(_) => return Ok(None),
+                };
+                if n == 0 || total == 0 {
+                    return Err(UnsatisfiableRange);
+                }
+                (total.saturating_sub(n), total - 1)
+            }
+            (Ok(start), "") => (start, total.saturating_sub(1)),
+            (Ok(start), end) => match end.parse::<u64>() {
+                Ok(end) if end >= start => (start, end.min(total.saturating_sub(1))),
+                _ => return Ok(None),
+            },
+            (Err(_), _) => return Ok(None),
+        };
+
+        if start >= total {
+            return Err(UnsatisfiableRange);
+        }
+        Ok(Some(ByteRange { start, end, total }))
+    }
+
+    /// The number of bytes within this range.
+    pub fn size(&self) -> u64 {
+        self.end - self.start + 1
+    }
+}
@@ -1,4 +1,8 @@
+mod conditional;
+mod problem;
 mod response;
+pub use crate::conditional::*;
+pub use crate::problem::Problem;
 pub use crate::response::*;
 
 #[cfg(not(tarpaulin_include))]
@@ -6,7 +10,7 @@ pub use crate::response::*;
 mod tests {
     use rocket::http::Status;
 
-    use super::{Data, Response};
+    use super::{ByteRange, Data, FileRequest, Problem, Response, UnsatisfiableRange};
 
     #[test]
     fn responder_basics() {
@@ -25,4 +29,76 @@ mod tests {
             _ => panic!("Invalid type!"),
         }
     }
+
+    #[test]
+    fn problem_basics() {
+        let problem = Problem::new(Status::BadRequest, "too_long", "Phrase is too long!")
+            .with_field("word", "hello");
+
+        assert_eq!(problem.status(), Status::BadRequest);
+        assert_eq!(problem.title, "Bad Request");
+        assert_eq!(problem.code, "too_long");
+        assert_eq!(problem.field.as_deref(), Some("word"));
+        assert_eq!(problem.value.as_deref(), Some("hello"));
+    }
+
+    #[test]
+    fn byte_ranges() {
+        let range = |start, end| {
+            Ok(Some(ByteRange {
+                start,
+                end,
+                total: 1000,
+            }))
+        };
+
+        assert_eq!(ByteRange::parse("bytes=0-99", 1000), range(0, 99));
+        assert_eq!(ByteRange::parse("bytes=500-", 1000), range(500, 999));
+        assert_eq!(ByteRange::parse("bytes=-100", 1000), range(900, 999));
+        assert_eq!(ByteRange::parse("bytes=900-5000", 1000), range(900, 999));
+        assert_eq!(ByteRange::parse("bytes=-5000", 1000), range(0, 999));
+        assert_eq!(
+            ByteRange::parse("bytes=0-99", 1000)
+                .unwrap()
+                .unwrap()
+                .size(),
+            100
+        );
+
+        //Ranges outside of the file cannot be satisfied
+        assert_eq!(
+            ByteRange::parse("bytes=1000-", 1000),
+            Err(UnsatisfiableRange)
+        );
+        assert_eq!(ByteRange::parse("bytes=-0", 1000), Err(UnsatisfiableRange));
+
+        //Malformed and multiple ranges are ignored
+        assert_eq!(ByteRange::parse("bytes=100-50", 1000), Ok(None));
+        assert_eq!(ByteRange::parse("bytes=0-1,5-6", 1000), Ok(None));
+        assert_eq!(ByteRange::parse("items=0-1", 1000), Ok(None));
+        assert_eq!(ByteRange::parse("bytes=a-b", 1000), Ok(None));
+    }
+
+    #[test]
+    fn etag_validation() {
+        let etag = "\"abc.wav\"";
+        let req = |if_none_match, if_range| FileRequest {
+            if_none_match,
+            if_range,
+            range: Some("bytes=0-1"),
+        };
+
+        assert!(req(Some("\"abc.wav\""), None).not_modified(etag));
+        assert!(req(Some("\"xyz.wav\", W/\"abc.wav\""), None).not_modified(etag));
+        assert!(req(Some("*"), None).not_modified(etag));
+        assert!(!req(Some("\"xyz.wav\""), None).not_modified(etag));
+        assert!(!req(None, None).not_modified(etag));
+
+        assert_eq!(req(None, None).range(etag), Some("bytes=0-1"));
+        assert_eq!(
+            req(None, Some("\"abc.wav\"")).range(etag),
+            Some("bytes=0-1")
+        );
+        assert_eq!(req(None, Some("\"xyz.wav\"")).range(etag), None);
+    }
 }
@@ -0,0 +1,49 @@
+//! Error responses following RFC 7807, `application/problem+json`.
+
+use rocket::{http::Status, serde::Serialize};
+
+/// A description of an error, which is returned to the user as `application/problem+json`.
+/// The `code` is stable, and should be used by clients to identify an error rather than the `detail`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Problem {
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+    pub code: String,
+    /// The field of the request which caused the error, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    /// The offending value of `field`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+}
+
+impl Problem {
+    /// Create a new problem, the title is taken from the reason phrase of the status.
+    pub fn new<C: Into<String>, D: Into<String>>(status: Status, code: C, detail: D) -> Self {
+        Problem {
+            type_: String::from("about:blank"),
+            title: status.reason_lossy().to_owned(),
+            status: status.code,
+            detail: detail.into(),
+            code: code.into(),
+            field: None,
+            value: None,
+        }
+    }
+
+    /// Attach the field, and the offending value of that field, which caused this problem.
+    pub fn with_field<F: Into<String>, V: Into<String>>(mut self, field: F, value: V) -> Self {
+        self.field = Some(field.into());
+        self.value = Some(value.into());
+        self
+    }
+
+    /// The status of this problem.
+    pub fn status(&self) -> Status {
+        Status::from_code(self.status).unwrap_or(Status::InternalServerError)
+    }
+}
@@ -1,6 +1,14 @@
 //! A custom response api for the festival-api.
 
-use rocket::{fs::NamedFile, http::Status, response::Responder, Request};
+use rocket::{
+    fs::NamedFile,
+    http::{ContentType, Status},
+    response::Responder,
+    serde::json::serde_json,
+    FromFormField, Request,
+};
+
+use crate::{ByteRange, Problem};
 
 /// Internal data that must be passed to a responder. Any data may be passed, but it must
 /// implement `rocket::response::Responder`.
@@ -29,16 +37,96 @@ where
     }
 }
 
+/// How a client should present a downloaded file, either played directly or saved to disk.
+/// Requested by clients through the `disposition` query parameter.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, FromFormField)]
+pub enum Disposition {
+    #[field(value = "inline")]
+    Inline,
+    #[default]
+    #[field(value = "attachment")]
+    Attachment,
+}
+
+impl std::fmt::Display for Disposition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Disposition::Inline => write!(f, "inline"),
+            Disposition::Attachment => write!(f, "attachment"),
+        }
+    }
+}
+
+/// Describes a file being downloaded, used to generate the content-type, content-disposition and caching headers.
+#[derive(Debug, Clone)]
+pub struct FileMeta {
+    pub filename: String,
+    pub content_type: ContentType,
+    pub disposition: Disposition,
+    /// A quoted, strong validator which changes whenever the content of the file does.
+    pub etag: String,
+    /// A stable url from which this file may be retrieved again.
+    pub location: String,
+    /// How long, in seconds, a client may reuse this file without revalidating it. 0 requires revalidation on every use.
+    pub max_age: u32,
+}
+
+impl FileMeta {
+    /// The caching headers sent alongside every version of this file.
+    fn caching_headers(&self) -> [rocket::http::Header<'static>; 4] {
+        let cache_control = match self.max_age {
+            0 => String::from("no-cache"),
+            age => format!("private, max-age={}", age),
+        };
+        [
+            rocket::http::Header::new("ETag", self.etag.clone()),
+            rocket::http::Header::new("Cache-Control", cache_control),
+            rocket::http::Header::new("Accept-Ranges", "bytes"),
+            rocket::http::Header::new("Content-Location", self.location.clone()),
+        ]
+    }
+}
+
 /// Represents a response from the api, the content-type and content-disposition headers are automatically generated.
 /// This is automatically generated from calling `.build()` on a `ResponseBuilder`. Do not attempt to generate this
 /// manually.
 #[derive(Debug)]
 pub enum Response {
-    TextErr(Data<String>),
+    /// An error, returned as `application/problem+json`.
+    Problem(Problem),
     TextOk(Data<String>),
-    #[allow(dead_code)]
     JsonOk(Data<String>),
-    FileDownload((Data<NamedFile>, String)),
+    HtmlOk(Data<String>),
+    FileDownload((Data<NamedFile>, FileMeta)),
+    /// A single range of a file, sent with a `206 Partial Content` status.
+    PartialDownload((Data<Vec<u8>>, FileMeta, ByteRange)),
+    /// The client already holds the current version of the file, sent with a `304 Not Modified` status.
+    NotModified(FileMeta),
+    ArchiveDownload((Data<Vec<u8>>, String)),
+    CsvDownload((Data<String>, String)),
+    /// A rejection due to rate limiting, alongside the number of seconds until the client may retry.
+    RateLimited((Problem, u64)),
+    /// A rejection due to a requested range lying outside of a file, alongside the size of the file.
+    RangeNotSatisfiable((Problem, u64)),
+}
+
+impl Response {
+    /// The status this response will be sent with.
+    pub fn status(&self) -> Status {
+        match *self {
+            Response::Problem(ref p) => p.status(),
+            Response::TextOk(ref d) => d.status,
+            Response::JsonOk(ref d) => d.status,
+            Response::HtmlOk(ref d) => d.status,
+            Response::FileDownload(ref d) => d.0.status,
+            Response::PartialDownload(ref d) => d.0.status,
+            Response::NotModified(_) => Status::NotModified,
+            Response::ArchiveDownload(ref d) => d.0.status,
+            Response::CsvDownload(ref d) => d.0.status,
+            Response::RateLimited(ref d) => d.0.status(),
+            Response::RangeNotSatisfiable(ref d) => d.0.status(),
+        }
+    }
 }
 
 #[rocket::async_trait]
@@ -46,37 +134,86 @@ impl<'r> rocket::response::Responder<'r, 'static> for Response {
     fn respond_to(self, req: &'r Request<'_>) -> rocket::response::Result<'static> {
         //Generate content type header
         let c_type = match self {
-            Response::TextErr(_) | Response::TextOk(_) => {
-                rocket::http::ContentType::new("text", "plain; charset=utf-8")
+            Response::TextOk(_) => rocket::http::ContentType::new("text", "plain; charset=utf-8"),
+            Response::Problem(_) | Response::RateLimited(_) | Response::RangeNotSatisfiable(_) => {
+                rocket::http::ContentType::new("application", "problem+json; charset=utf-8")
             }
             Response::JsonOk(_) => {
                 rocket::http::ContentType::new("application", "json; charset=utf-8")
             }
-            Response::FileDownload(_) => rocket::http::ContentType::new("audio", "mpeg"),
+            Response::HtmlOk(_) => rocket::http::ContentType::new("text", "html; charset=utf-8"),
+            Response::FileDownload(ref d) => d.1.content_type.clone(),
+            Response::PartialDownload(ref d) => d.1.content_type.clone(),
+            Response::NotModified(ref m) => m.content_type.clone(),
+            Response::ArchiveDownload(_) => rocket::http::ContentType::new("application", "zip"),
+            Response::CsvDownload(_) => {
+                rocket::http::ContentType::new("text", "csv; charset=utf-8")
+            }
         };
 
         //Generate content disposition header
         let c_disp = match self {
-            Response::FileDownload(ref d) => rocket::http::Header::new(
+            Response::FileDownload((_, ref m))
+            | Response::PartialDownload((_, ref m, _))
+            | Response::NotModified(ref m) => rocket::http::Header::new(
+                "Content-Disposition",
+                format!("{}; filename=\"{}\"", m.disposition, m.filename),
+            ),
+            Response::ArchiveDownload(ref d) => rocket::http::Header::new(
+                "Content-Disposition",
+                format!("attachment; filename=\"{}\"", d.1),
+            ),
+            Response::CsvDownload(ref d) => rocket::http::Header::new(
                 "Content-Disposition",
                 format!("attachment; filename=\"{}\"", d.1),
             ),
             _ => rocket::http::Header::new("Content-Disposition", "inline"),
         };
 
-        let status: Status = match self {
-            Response::TextErr(ref d) => d.status,
-            Response::TextOk(ref d) => d.status,
-            Response::JsonOk(ref d) => d.status,
-            Response::FileDownload(ref d) => d.0.status,
+        //Generate retry after header
+        let retry_after = match self {
+            Response::RateLimited(ref d) => {
+                Some(rocket::http::Header::new("Retry-After", d.1.to_string()))
+            }
+            _ => None,
+        };
+
+        //Generate caching and range headers
+        let mut file_headers = match self {
+            Response::FileDownload((_, ref m))
+            | Response::PartialDownload((_, ref m, _))
+            | Response::NotModified(ref m) => m.caching_headers().to_vec(),
+            _ => vec![],
         };
+        match self {
+            Response::PartialDownload((_, _, ref r)) => {
+                file_headers.push(rocket::http::Header::new(
+                    "Content-Range",
+                    format!("bytes {}-{}/{}", r.start, r.end, r.total),
+                ))
+            }
+            Response::RangeNotSatisfiable(ref d) => file_headers.push(rocket::http::Header::new(
+                "Content-Range",
+                format!("bytes */{}", d.1),
+            )),
+            _ => {}
+        }
+
+        let status = self.status();
 
         //Construct and return response
         let response = match self {
-            Response::TextErr(d) => d.data.respond_to(req),
+            Response::Problem(p) => problem_body(&p).respond_to(req),
             Response::TextOk(d) => d.data.respond_to(req),
             Response::JsonOk(d) => d.data.respond_to(req),
+            Response::HtmlOk(d) => d.data.respond_to(req),
             Response::FileDownload(d) => d.0.data.respond_to(req),
+            Response::PartialDownload(d) => d.0.data.respond_to(req),
+            Response::NotModified(_) => ().respond_to(req),
+            Response::ArchiveDownload(d) => d.0.data.respond_to(req),
+            Response::CsvDownload(d) => d.0.data.respond_to(req),
+            Response::RateLimited(d) => problem_body(&d.0).respond_to(req),
+            Response::RangeNotSatisfiable(d) => problem_body(&d.0).respond_to(req),
         };
 
         let mut response = response.unwrap(); //HACK
@@ -84,10 +221,26 @@ impl<'r> rocket::response::Responder<'r, 'static> for Response {
         response.set_header(c_type);
         response.set_header(c_disp);
         response.set_status(status);
+        if let Some(retry_after) = retry_after {
+            response.set_header(retry_after);
+        }
+        for header in file_headers {
+            response.set_header(header);
+        }
         Ok(response)
     }
 }
 
+/// Serialize a problem into the body of a response.
+fn problem_body(problem: &Problem) -> String {
+    serde_json::to_string(problem).unwrap_or_else(|_| {
+        format!(
+            "{{\"type\":\"about:blank\",\"status\":{},\"code\":\"{}\"}}",
+            problem.status, problem.code
+        )
+    })
+}
+
 // XXX implement once https://github.com/rust-lang/rust/issues/84277 is stabilised
 // Then all endpoints in `main.rs` can simply return Response, rather than Result<Response, Response>.
 // impl<'a> std::ops::FromResidual<Result<std::convert::Infallible, response::Response<'_>>> for Response<'a> {
@@ -1,5 +1,88 @@
-use config::Config;
+use std::{borrow::Cow, ops::Range, path::PathBuf};
+
+use config::{BlacklistPolicy, Config};
 use serde::Deserialize;
+use unicode_normalization::{is_nfc, UnicodeNormalization};
+
+/// The reasons a phrase package may fail validation.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationError {
+    UnsupportedLang(String),
+    UnsupportedFormat(String),
+    MissingFormat,
+    TooLong { limit: usize, word: String },
+    Empty,
+    BlacklistedPhrase(String),
+    InvalidChar(char),
+}
+
+impl ValidationError {
+    /// A stable, machine readable code for this error.
+    pub fn code(&self) -> &'static str {
+        match *self {
+            ValidationError::UnsupportedLang(_) => "unsupported_lang",
+            ValidationError::UnsupportedFormat(_) => "unsupported_format",
+            ValidationError::MissingFormat => "missing_format",
+            ValidationError::TooLong { .. } => "too_long",
+            ValidationError::Empty => "empty_phrase",
+            ValidationError::BlacklistedPhrase(_) => "blacklisted_phrase",
+            ValidationError::InvalidChar(_) => "invalid_char",
+        }
+    }
+
+    /// The field of the phrase package which caused this error.
+    pub fn field(&self) -> &'static str {
+        match *self {
+            ValidationError::UnsupportedLang(_) => "lang",
+            ValidationError::UnsupportedFormat(_) | ValidationError::MissingFormat => "fmt",
+            _ => "word",
+        }
+    }
+
+    /// The offending value of the field which caused this error.
+    pub fn value(&self) -> String {
+        match *self {
+            ValidationError::UnsupportedLang(ref v)
+            | ValidationError::UnsupportedFormat(ref v)
+            | ValidationError::BlacklistedPhrase(ref v) => v.clone(),
+            ValidationError::TooLong { ref word, .. } => word.clone(),
+            ValidationError::Empty | ValidationError::MissingFormat => String::new(),
+            ValidationError::InvalidChar(c) => c.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            ValidationError::UnsupportedLang(ref l) => {
+                write!(f, "Provided lang ({}) is not supported by this api!", l)
+            }
+            ValidationError::UnsupportedFormat(ref fmt) => {
+                write!(
+                    f,
+                    "Requested format ({}) is not supported by this api!",
+                    fmt
+                )
+            }
+            ValidationError::MissingFormat => write!(f, "No format provided!"),
+            ValidationError::TooLong { limit, .. } => {
+                write!(f, "Phrase is too long! Greater than {} chars", limit)
+            }
+            ValidationError::Empty => write!(f, "No word provided!"),
+            ValidationError::BlacklistedPhrase(ref p) => {
+                write!(f, "Blacklisted word! Phrase ({}) is not allowed!", p)
+            }
+            ValidationError::InvalidChar(c) => write!(
+                f,
+                "Char ({}) is not allowed to be sent to this api! Please try again.",
+                c
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
 
 /// A phrase package which the user is requesting a speech to be generated for.
 #[derive(Deserialize)]
@@ -7,23 +90,113 @@ pub struct PhrasePackage {
     pub word: String,
     pub lang: String,
     pub speed: f32,
-    pub fmt: String,
+    /// The format of the sound file, None if not provided so that an endpoint may choose one on behalf of the user.
+    #[serde(default)]
+    pub fmt: Option<String>,
+    /// What is done with blacklisted phrases, overriding `BLACKLIST_POLICY` for this phrase.
+    #[serde(default)]
+    pub blacklist_policy: Option<BlacklistPolicy>,
+    /// The ranges of `word` which are to be replaced by a tone, found by `validated()` when bleeping.
+    #[serde(skip)]
+    pub bleeps: Vec<Range<usize>>,
 }
 
 impl PhrasePackage {
+    /// The phrase in Unicode normalisation form C, so that a macron typed as a combining char reads, and is cached,
+    /// the same as its precomposed form.
+    fn normalised_word(&self) -> Cow<'_, str> {
+        match is_nfc(&self.word) {
+            true => Cow::Borrowed(&self.word),
+            false => Cow::Owned(self.word.nfc().collect()),
+        }
+    }
+
+    /// Distinguishes the files of a bleeped phrase from those of the phrase read in full.
+    fn bleep_suffix(&self) -> String {
+        self.bleeps
+            .iter()
+            .map(|r| format!("_bleep{}-{}", r.start, r.end))
+            .collect()
+    }
+
     /// Generate a filename, minus the file extension
     pub fn filename_stem_properspeed(&self) -> String {
-        crate::sha_256_hash(&format!("{}_{}_{}", self.word, self.lang, self.speed))
+        crate::sha_256_hash(&format!(
+            "{}_{}_{}{}",
+            self.normalised_word(),
+            self.lang,
+            self.speed,
+            self.bleep_suffix()
+        ))
     }
 
     /// Collect the name of the file pre-conversion or speed change
     pub fn filename_stem_basespeed(&self) -> String {
-        crate::sha_256_hash(&format!("{}_{}_1.0", self.word, self.lang))
+        crate::sha_256_hash(&format!(
+            "{}_{}_1.0{}",
+            self.normalised_word(),
+            self.lang,
+            self.bleep_suffix()
+        ))
+    }
+
+    /// Every file stem this phrase may be stored under in the cache, whether read in full or bleeped, at the base
+    /// speed and at every speed that validation allows. Files with these stems hold this phrase in any format.
+    pub fn cached_stems(&self, cfg: &Config) -> Vec<String> {
+        let mut speeds = vec![cfg.SPEED_MIN_VAL(), cfg.SPEED_MAX_VAL()];
+        let mut speed = (cfg.SPEED_MIN_VAL() * 2.0).ceil() / 2.0;
+        while speed < cfg.SPEED_MAX_VAL() {
+            speeds.push(speed);
+            speed += 0.5;
+        }
+
+        let mut pack = PhrasePackage {
+            word: self.normalised_word().into_owned(),
+            lang: self.lang.clone(),
+            speed: 1.0,
+            fmt: None,
+            blacklist_policy: None,
+            bleeps: vec![],
+        };
+        let bleeps = cfg.BLACKLIST().matches(&pack.word, &pack.lang);
+
+        let mut stems = vec![];
+        for bleeps in [vec![], bleeps] {
+            pack.bleeps = bleeps;
+            stems.push(pack.filename_stem_basespeed());
+            for speed in speeds.iter() {
+                pack.speed = *speed;
+                stems.push(pack.filename_stem_properspeed());
+            }
+        }
+        stems.sort_unstable();
+        stems.dedup();
+        stems
+    }
+
+    /// The path in the cache at which this phrase will be stored once generated, before conversion or speed change.
+    pub fn generated_path(&self, cfg: &Config) -> PathBuf {
+        PathBuf::from(cfg.CACHE_PATH()).join(format!("{}.wav", self.filename_stem_basespeed()))
+    }
+
+    /// The requested format of the sound file, which is always present once the package has been validated.
+    pub fn format(&self) -> &str {
+        self.fmt.as_deref().unwrap_or_default()
+    }
+
+    /// The path in the cache at which this phrase will be stored once converted to the requested format and speed.
+    pub fn converted_path(&self, cfg: &Config) -> PathBuf {
+        PathBuf::from(cfg.CACHE_PATH()).join(format!(
+            "{}.{}",
+            self.filename_stem_properspeed(),
+            self.format()
+        ))
     }
 
     /// Validates (and attempts to fix) a phrase package.
     /// Returns Ok() if the package is valid, and Err otherwise.
     /// Attempts to correct:
+    /// - Phrases which are not in Unicode normalisation form C
     /// - Speed values larger or smaller than the allowed values
     /// - Speed values that are not divisible by 0.5
     ///
@@ -32,8 +205,10 @@ impl PhrasePackage {
     /// - Invalid file format selection
     /// - Phrase too long
     /// - Phrase contains invalid chars (TBD)
-    /// - Phrase contains invalid phrases
-    pub fn validated(&mut self, cfg: &Config) -> Result<(), String> {
+    /// - Phrase contains invalid phrases, unless they are to be skipped or bleeped
+    pub fn validated(&mut self, cfg: &Config) -> Result<(), ValidationError> {
+        self.word = self.normalised_word().into_owned();
+
         //Attempt to correct speed values
 
         if self.speed % 0.5 != 0.0 {
@@ -50,48 +225,71 @@ impl PhrasePackage {
 
         //Check language selection is valid
         if !cfg.SUPPORTED_LANGS().contains_key(&self.lang) {
-            return Err(format!(
-                "Provided lang ({}) is not supported by this api!",
-                &self.lang
-            ));
+            return Err(ValidationError::UnsupportedLang(self.lang.clone()));
         }
 
         //Validate fild format selection
-        if !cfg.ALLOWED_FORMATS().contains(&self.fmt) {
-            return Err(format!(
-                "Requested format ({}) is not supported by this api!",
-                &self.fmt
-            ));
+        match self.fmt {
+            None => return Err(ValidationError::MissingFormat),
+            Some(ref fmt) if !cfg.ALLOWED_FORMATS().contains(fmt) => {
+                return Err(ValidationError::UnsupportedFormat(fmt.clone()))
+            }
+            Some(_) => {}
         }
 
         //Check that provided phrase is valid
-        if self.word.len() > cfg.WORD_LENGTH_LIMIT() {
-            return Err(format!(
-                "Phrase is too long! Greater than {} chars",
-                cfg.WORD_LENGTH_LIMIT()
-            ));
+        if self.word.chars().count() > cfg.WORD_LENGTH_LIMIT() {
+            return Err(ValidationError::TooLong {
+                limit: cfg.WORD_LENGTH_LIMIT(),
+                word: self.word.clone(),
+            });
         }
         if self.word.is_empty() {
-            return Err(String::from("No word provided!"));
+            return Err(ValidationError::Empty);
         }
 
-        //Validate that the nothing from the blacklist is present
-        let match_phrase = format!(" {} ", self.word);
-        for phrase in cfg.BLACKLISTED_PHRASES().iter() {
-            if match_phrase.contains(phrase) {
-                return Err(format!(
-                    "Blacklisted word! Phrase ({}) is not allowed!",
-                    phrase.trim()
-                ));
+        //Validate that the nothing from the blacklist is present, or handle it as requested
+        let policy = self
+            .blacklist_policy
+            .unwrap_or_else(|| cfg.BLACKLIST_POLICY());
+        if policy != BlacklistPolicy::Reject {
+            //Blacklisted phrases which can't be located can't be removed or replaced, so are always rejected
+            if let Some(phrase) = cfg.BLACKLIST().unlocatable(&self.word) {
+                return Err(ValidationError::BlacklistedPhrase(phrase.to_owned()));
+            }
+        }
+        match policy {
+            BlacklistPolicy::Reject => {
+                if let Some(phrase) = cfg.BLACKLIST().find(&self.word, &self.lang) {
+                    return Err(ValidationError::BlacklistedPhrase(phrase.to_owned()));
+                }
+            }
+            BlacklistPolicy::Skip => {
+                let matches = cfg.BLACKLIST().matches(&self.word, &self.lang);
+                if !matches.is_empty() {
+                    let mut word = String::with_capacity(self.word.len());
+                    let mut last = 0;
+                    for m in matches {
+                        word.push_str(&self.word[last..m.start]);
+                        word.push(' ');
+                        last = m.end;
+                    }
+                    word.push_str(&self.word[last..]);
+
+                    self.word = word.split_whitespace().collect::<Vec<&str>>().join(" ");
+                    if self.word.is_empty() {
+                        return Err(ValidationError::Empty);
+                    }
+                }
+            }
+            BlacklistPolicy::Bleep => {
+                self.bleeps = cfg.BLACKLIST().matches(&self.word, &self.lang);
             }
         }
 
         for c in self.word.chars() {
             if !cfg.ALLOWED_CHARS().contains(&c) {
-                return Err(format!(
-                    "Char ({}) is not allowed to be sent to this api! Please try again.",
-                    c
-                ));
+                return Err(ValidationError::InvalidChar(c));
             }
         }
 
@@ -103,7 +301,7 @@ impl PhrasePackage {
 mod tests {
     use std::path::PathBuf;
 
-    use super::PhrasePackage;
+    use super::{PhrasePackage, ValidationError};
     use crate::generate_random_alphanumeric;
     use config::Config;
 
@@ -115,7 +313,9 @@ mod tests {
             word: String::from("Hello, world!"),
             lang: String::from("en"),
             speed: cfg.SPEED_MAX_VAL(),
-            fmt: String::from("mp3"),
+            fmt: Some(String::from("mp3")),
+            blacklist_policy: None,
+            bleeps: vec![],
         };
         pack.validated(&cfg).expect("a valid package");
 
@@ -123,7 +323,9 @@ mod tests {
             word: String::from("Hello, world!"),
             lang: String::from("en"),
             speed: cfg.SPEED_MIN_VAL(),
-            fmt: String::from("mp3"),
+            fmt: Some(String::from("mp3")),
+            blacklist_policy: None,
+            bleeps: vec![],
         };
         pack.validated(&cfg).expect("a valid package");
 
@@ -131,7 +333,9 @@ mod tests {
             word: String::from("H"),
             lang: String::from("en"),
             speed: cfg.SPEED_MIN_VAL(),
-            fmt: String::from("mp3"),
+            fmt: Some(String::from("mp3")),
+            blacklist_policy: None,
+            bleeps: vec![],
         };
         pack.validated(&cfg).expect("a valid package");
 
@@ -139,7 +343,9 @@ mod tests {
             word: generate_random_alphanumeric(cfg.WORD_LENGTH_LIMIT()),
             lang: String::from("en"),
             speed: cfg.SPEED_MIN_VAL(),
-            fmt: String::from("mp3"),
+            fmt: Some(String::from("mp3")),
+            blacklist_policy: None,
+            bleeps: vec![],
         };
         pack.validated(&cfg).expect("a valid package");
     }
@@ -159,7 +365,9 @@ mod tests {
             word: String::from("Hello, world!"),
             lang: String::from("en"),
             speed: cfg.SPEED_MIN_VAL() - 0.1,
-            fmt: String::from("mp3"),
+            fmt: Some(String::from("mp3")),
+            blacklist_policy: None,
+            bleeps: vec![],
         };
 
         // Validate the max value correct is in place!
@@ -170,7 +378,9 @@ mod tests {
             word: String::from("Hello, world!"),
             lang: String::from("en"),
             speed: cfg.SPEED_MAX_VAL() + 0.1,
-            fmt: String::from("mp3"),
+            fmt: Some(String::from("mp3")),
+            blacklist_policy: None,
+            bleeps: vec![],
         };
 
         pack.validated(&cfg).expect("a valid package");
@@ -182,7 +392,9 @@ mod tests {
                 word: String::from("Hello, world!"),
                 lang: String::from("en"),
                 speed: 0.0 + 0.35 * i as f32,
-                fmt: String::from("mp3"),
+                fmt: Some(String::from("mp3")),
+                blacklist_policy: None,
+                bleeps: vec![],
             };
 
             pack.validated(&cfg).expect("a valid package");
@@ -200,30 +412,44 @@ mod tests {
             word: String::from(""),
             lang: String::from("en"),
             speed: cfg.SPEED_MIN_VAL(),
-            fmt: String::from("mp3"),
+            fmt: Some(String::from("mp3")),
+            blacklist_policy: None,
+            bleeps: vec![],
         };
 
-        pack.validated(&cfg).expect_err("should be too short");
+        let e = pack.validated(&cfg).expect_err("should be too short");
+        assert_eq!(e, ValidationError::Empty);
+        assert_eq!(e.code(), "empty_phrase");
 
         //Test string too long
         let mut pack = PhrasePackage {
             word: generate_random_alphanumeric(cfg.WORD_LENGTH_LIMIT() + 1),
             lang: String::from("en"),
             speed: cfg.SPEED_MIN_VAL(),
-            fmt: String::from("mp3"),
+            fmt: Some(String::from("mp3")),
+            blacklist_policy: None,
+            bleeps: vec![],
         };
 
-        pack.validated(&cfg).expect_err("should be too long");
+        let e = pack.validated(&cfg).expect_err("should be too long");
+        assert_eq!(e.code(), "too_long");
+        assert_eq!(e.field(), "word");
+        assert_eq!(e.value(), pack.word);
 
         //Test unsupported lang
         let mut pack = PhrasePackage {
             word: String::from("a wiord"),
             lang: String::from("adfadlfjalk"),
             speed: cfg.SPEED_MIN_VAL(),
-            fmt: String::from("mp3"),
+            fmt: Some(String::from("mp3")),
+            blacklist_policy: None,
+            bleeps: vec![],
         };
 
-        pack.validated(&cfg).expect_err("should be invalid lang");
+        let e = pack.validated(&cfg).expect_err("should be invalid lang");
+        assert_eq!(e.code(), "unsupported_lang");
+        assert_eq!(e.field(), "lang");
+        assert_eq!(e.value(), "adfadlfjalk");
     }
 
     #[test]
@@ -234,16 +460,36 @@ mod tests {
             word: String::from("hello"),
             lang: String::from("en"),
             speed: cfg.SPEED_MIN_VAL(),
-            fmt: String::from("format"),
+            fmt: Some(String::from("format")),
+            blacklist_policy: None,
+            bleeps: vec![],
         };
         if let Err(e) = pack.validated(&cfg) {
             assert_eq!(
-                e,
+                e.to_string(),
                 String::from("Requested format (format) is not supported by this api!")
-            )
+            );
+            assert_eq!(e.code(), "unsupported_format");
+            assert_eq!(e.field(), "fmt");
+            assert_eq!(e.value(), "format");
         } else {
             panic!("Unexpected response!")
         }
+
+        //An empty format is not the same as one which was not provided
+        pack.fmt = Some(String::new());
+        let e = pack
+            .validated(&cfg)
+            .expect_err("should be an unsupported format");
+        assert_eq!(e.code(), "unsupported_format");
+
+        pack.fmt = None;
+        let e = pack
+            .validated(&cfg)
+            .expect_err("should be missing a format");
+        assert_eq!(e, ValidationError::MissingFormat);
+        assert_eq!(e.code(), "missing_format");
+        assert_eq!(e.field(), "fmt");
     }
 
     #[test]
@@ -255,10 +501,72 @@ mod tests {
                 word: String::from("hello"),
                 lang: String::from("en"),
                 speed: cfg.SPEED_MIN_VAL(),
-                fmt: format.clone(),
+                fmt: Some(format.clone()),
+                blacklist_policy: None,
+                bleeps: vec![],
             };
 
             pack.validated(&cfg).expect("a valid pack");
         }
     }
+
+    #[test]
+    fn normalised_phrases() {
+        let cfg = Config::new(PathBuf::from("../../../config")).unwrap();
+
+        let pack = |word: String| PhrasePackage {
+            word,
+            lang: String::from("en"),
+            speed: cfg.SPEED_MIN_VAL(),
+            fmt: Some(String::from("wav")),
+            blacklist_policy: None,
+            bleeps: vec![],
+        };
+        let precomposed = pack(String::from("Kia ora, \u{101}e"));
+
+        // A macron typed as a combining char is accepted, and cached as the precomposed form
+        let mut decomposed = pack(String::from("Kia ora, a\u{304}e"));
+        assert_eq!(
+            decomposed.filename_stem_properspeed(),
+            precomposed.filename_stem_properspeed()
+        );
+        assert_eq!(
+            decomposed.filename_stem_basespeed(),
+            precomposed.filename_stem_basespeed()
+        );
+        decomposed.validated(&cfg).expect("a valid package");
+        assert_eq!(decomposed.word, precomposed.word);
+
+        // The length limit counts chars, not bytes
+        pack("\u{101}".repeat(cfg.WORD_LENGTH_LIMIT()))
+            .validated(&cfg)
+            .expect("a valid package");
+        pack("a\u{304}".repeat(cfg.WORD_LENGTH_LIMIT()))
+            .validated(&cfg)
+            .expect("a valid package");
+        pack("\u{101}".repeat(cfg.WORD_LENGTH_LIMIT() + 1))
+            .validated(&cfg)
+            .expect_err("should be too long");
+    }
+
+    #[test]
+    fn cached_stems() {
+        let cfg = Config::new(PathBuf::from("../../../config")).unwrap();
+
+        let mut pack = PhrasePackage {
+            word: String::from("Hello, world!"),
+            lang: String::from("en"),
+            speed: cfg.SPEED_MAX_VAL(),
+            fmt: Some(String::from("mp3")),
+            blacklist_policy: None,
+            bleeps: vec![],
+        };
+        pack.validated(&cfg).expect("a valid package");
+
+        let stems = pack.cached_stems(&cfg);
+        assert!(stems.contains(&pack.filename_stem_basespeed()));
+        assert!(stems.contains(&pack.filename_stem_properspeed()));
+        pack.speed = cfg.SPEED_MIN_VAL();
+        assert!(stems.contains(&pack.filename_stem_properspeed()));
+    }
 }
@@ -2,6 +2,12 @@ use config::PathType;
 use std::path::PathBuf;
 
 //***** Helper Methods *****//
+/// The api key of the user added by `AlteredToml::with_test_user()`.
+pub const TEST_API_KEY: &str = "test-api-key-not-for-production-use";
+
+/// The api key of the administrator added by `AlteredToml::with_test_admin()`.
+pub const TEST_ADMIN_KEY: &str = "test-admin-key-not-for-production-use";
+
 /// A simple struct which allows a property on toml to be changed.
 pub struct AlteredToml(PathType, String, PathBuf);
 
@@ -22,6 +28,32 @@ impl AlteredToml {
         //Save and return
         AlteredToml(p_type, data, replace_path)
     }
+
+    /// Adds a user to `users.toml` who may access authenticated endpoints by providing `TEST_API_KEY`.
+    pub fn with_test_user(replace_path: PathBuf) -> Self {
+        Self::new(
+            "\n[user]\n",
+            &format!(
+                "\n[user]\n\n[user.test]\nid = 0\nkey = \"{}\"\nenabled = true\n",
+                TEST_API_KEY
+            ),
+            PathType::Users,
+            replace_path,
+        )
+    }
+
+    /// Adds an administrator to `users.toml` who may access administrative endpoints by providing `TEST_ADMIN_KEY`.
+    pub fn with_test_admin(replace_path: PathBuf) -> Self {
+        Self::new(
+            "\n[user]\n",
+            &format!(
+                "\n[user]\n\n[user.admin]\nid = -1\nkey = \"{}\"\nenabled = true\nadmin = true\n",
+                TEST_ADMIN_KEY
+            ),
+            PathType::Users,
+            replace_path,
+        )
+    }
 }
 
 impl Drop for AlteredToml {
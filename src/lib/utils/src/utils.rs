@@ -18,11 +18,36 @@ pub fn sha_256_hash(input: &str) -> String {
     format!("{:x}", hasher.finalize())
 }
 
+/// Escape a string so that it may be safely placed within the text or attribute values of an html page.
+pub fn escape_html(input: &str) -> String {
+    let mut escaped = String::with_capacity(input.len());
+    for c in input.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#x27;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
 #[cfg(test)]
 mod test {
     use std::collections::HashSet;
 
-    use crate::generate_random_alphanumeric;
+    use crate::{escape_html, generate_random_alphanumeric};
+
+    #[test]
+    fn test_escape_html() {
+        assert_eq!(escape_html("Te Reo Māori"), "Te Reo Māori");
+        assert_eq!(
+            escape_html("<script>alert('a & \"b\"')</script>"),
+            "&lt;script&gt;alert(&#x27;a &amp; &quot;b&quot;&#x27;)&lt;/script&gt;"
+        );
+    }
 
     #[test]
     fn test_generate_random_alphanumeric() {
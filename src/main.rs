@@ -1,109 +1,877 @@
 #![doc = include_str!("../readme.md")]
 
+pub mod admin;
+pub mod database;
+pub mod docs;
+pub mod health;
+pub mod jobs;
+pub mod metrics;
 pub mod models;
+pub mod share;
 
 #[macro_use]
 extern crate rocket;
 
 use cache_manager::Cache;
-use config::Config;
+use config::{
+    models::{Language, User},
+    BlacklistPolicy, Config, API_KEY_HEADER, API_KEY_QUERY,
+};
 use converter::{Converter, Ffmpeg};
+use database::Database;
+use docs::Docs;
 use festvox::{Flite, TtsGenerator};
-use macros::failure;
-use response::{Data, Response};
-use rocket::{fs::NamedFile, http::Status, serde::json::Json};
-use utils::phrase_package::PhrasePackage;
+use jobs::Jobs;
+use macros::{failure, reject};
+use metrics::{Metrics, RequestLabels};
+use models::{BatchManifestEntry, FormatCapability, ShareLink};
+use rate_limiter::{ClientId, RateLimiter};
+use response::{
+    ByteRange, Data, Disposition, FileMeta, FileRequest, Problem, Response, UnsatisfiableRange,
+};
+use rocket::{
+    fs::NamedFile,
+    futures::{stream, StreamExt},
+    http::{Accept, ContentType, Status},
+    serde::json::{serde_json, Json},
+    tokio::io::{AsyncReadExt, AsyncSeekExt},
+};
+use share::ShareLinks;
+use std::{
+    io::{Cursor, SeekFrom, Write},
+    path::PathBuf,
+    time::{Duration, Instant, UNIX_EPOCH},
+};
+use utils::{
+    escape_html,
+    phrase_package::{PhrasePackage, ValidationError},
+};
+use zip::{result::ZipError, write::FileOptions, CompressionMethod, ZipWriter};
 
 #[cfg(not(target_os = "linux"))]
 compile_error!("Unable to compile for your platform! This API is only available for Linux due to dependence on Bash commands.");
 
-/// The base url of the program. This is just a catch-all for those who stumble across the api without knowing what it does.
+/// The page served at the base url, see `index()`.
+const PLAYGROUND_PAGE: &str = include_str!("playground.html");
+
+/// The base url of the program. This is a playground for those who stumble across the api, allowing them to
+/// try converting phrases with any of the enabled languages and supported formats before writing code against it.
 #[get("/")]
-pub fn index(cfg: &Config) -> String {
-    format!("Welcome to {}'s TTS API.", cfg.API_NAME())
+pub fn index(cfg: &Config, converter: &Converter) -> Response {
+    let mut langs: Vec<&Language> = cfg
+        .SUPPORTED_LANGS()
+        .values()
+        .filter(|l| l.enabled)
+        .collect();
+    langs.sort_by(|a, b| a.display_name.cmp(&b.display_name));
+    let langs: String = langs
+        .iter()
+        .map(|l| {
+            format!(
+                "<option value=\"{}\">{}</option>",
+                escape_html(&l.iso_691_code),
+                escape_html(&l.display_name)
+            )
+        })
+        .collect();
+
+    let mut formats: Vec<&String> = cfg
+        .ALLOWED_FORMATS()
+        .iter()
+        .filter(|fmt| converter.is_supported(fmt))
+        .collect();
+    formats.sort_unstable();
+    let formats: String = formats
+        .iter()
+        .map(|fmt| {
+            let selected = if fmt.as_str() == "wav" {
+                " selected"
+            } else {
+                ""
+            };
+            format!(
+                "<option value=\"{0}\"{1}>{0}</option>",
+                escape_html(fmt),
+                selected
+            )
+        })
+        .collect();
+
+    let speed = 1.0_f32.max(cfg.SPEED_MIN_VAL()).min(cfg.SPEED_MAX_VAL());
+    let page = PLAYGROUND_PAGE
+        .replace("{{name}}", &escape_html(cfg.API_NAME()))
+        .replace("{{api_key_header}}", API_KEY_HEADER)
+        .replace("{{langs}}", &langs)
+        .replace("{{formats}}", &formats)
+        .replace("{{speed_min}}", &cfg.SPEED_MIN_VAL().to_string())
+        .replace("{{speed_max}}", &cfg.SPEED_MAX_VAL().to_string())
+        .replace("{{speed}}", &speed.to_string());
+
+    Response::HtmlOk(Data {
+        data: page,
+        status: Status::Ok,
+    })
+}
+
+/// Lists every language configured on this api, including those which are currently disabled.
+/// Intended to allow clients to populate a language picker without hard-coding the available options.
+#[get("/langs")]
+pub async fn langs(cfg: &Config) -> Result<Response, Response> {
+    let mut langs: Vec<&Language> = cfg.SUPPORTED_LANGS().values().collect();
+    langs.sort_by(|a, b| a.iso_691_code.cmp(&b.iso_691_code));
+
+    let data = match serde_json::to_string(&langs) {
+        Ok(d) => d,
+        Err(e) => failure!(serialization_failure, "unable to serialize languages {}", e),
+    };
+
+    Ok(Response::JsonOk(Data {
+        data,
+        status: Status::Ok,
+    }))
+}
+
+/// Lists every output format which is both allowed by the configuration and supported by one of the
+/// attached converters, alongside the converter responsible for it and the MIME type of the output.
+#[get("/formats")]
+pub async fn formats(cfg: &Config, converter: &Converter) -> Result<Response, Response> {
+    let mut formats: Vec<FormatCapability> = cfg
+        .ALLOWED_FORMATS()
+        .iter()
+        .filter(|fmt| converter.is_supported(fmt))
+        .filter_map(|fmt| {
+            Some(FormatCapability {
+                format: fmt,
+                converter: converter.handler(fmt)?.name(),
+                mime_type: converter.mime_type(fmt)?,
+            })
+        })
+        .collect();
+    formats.sort_by(|a, b| a.format.cmp(b.format));
+
+    let data = match serde_json::to_string(&formats) {
+        Ok(d) => d,
+        Err(e) => failure!(serialization_failure, "unable to serialize formats {}", e),
+    };
+
+    Ok(Response::JsonOk(Data {
+        data,
+        status: Status::Ok,
+    }))
+}
+
+/// Collects the problem from an error response, for reporting errors which are not returned directly to the user.
+pub(crate) fn into_problem(response: Response) -> Problem {
+    match response {
+        Response::Problem(p) | Response::RateLimited((p, _)) => p,
+        r => Problem::new(r.status(), "unknown_error", "an unknown error occured"),
+    }
+}
+
+/// Describes why a phrase package failed validation, including the field and value responsible.
+pub(crate) fn invalid_phrase(e: ValidationError) -> Problem {
+    Problem::new(Status::BadRequest, e.code(), e.to_string()).with_field(e.field(), e.value())
+}
+
+/// Chooses a format from the media types the user accepts, if they did not request one.
+/// Fails if none of the accepted media types may be produced.
+#[allow(clippy::result_large_err)]
+pub(crate) fn negotiate_format(
+    phrase_package: &mut PhrasePackage,
+    accept: Option<&Accept>,
+    converter: &Converter,
+    cfg: &Config,
+) -> Result<(), Problem> {
+    if phrase_package.fmt.is_some() {
+        return Ok(());
+    }
+    match converter.negotiate(accept, cfg.ALLOWED_FORMATS()) {
+        Some(fmt) => {
+            phrase_package.fmt = Some(fmt.to_owned());
+            Ok(())
+        }
+        None => Err(Problem::new(
+            Status::NotAcceptable,
+            "not_acceptable",
+            format!(
+                "None of the accepted media types ({}) may be produced by this api!",
+                accept.map(|a| a.to_string()).unwrap_or_default()
+            ),
+        )),
+    }
+}
+
+/// Generates a response for a client who has exceeded their rate limit, and must wait before retrying.
+pub(crate) fn rate_limited(wait: Duration) -> Response {
+    let retry_after = wait.as_secs_f64().ceil() as u64;
+    Response::RateLimited((
+        Problem::new(
+            Status::TooManyRequests,
+            "rate_limited",
+            format!(
+                "Rate limit exceeded! Please try again in {} seconds.",
+                retry_after
+            ),
+        ),
+        retry_after,
+    ))
+}
+
+/// Generates the provided phrase package, and converts it into the requested format.
+/// Returns the path to the converted file in the cache. The phrase package should be validated before calling this.
+pub(crate) async fn generate_and_convert(
+    phrase_package: &PhrasePackage,
+    generator: &Flite,
+    converter: &Converter,
+    cfg: &Config,
+    cache: &Cache,
+    metrics: &Metrics,
+) -> Result<PathBuf, Response> {
+    metrics.cache_lookup(phrase_package.converted_path(cfg).exists());
+
+    // Generate the phrase
+    let generated = phrase_package.generated_path(cfg).exists();
+    let start = Instant::now();
+    let generated_file = generator.generate(phrase_package, cfg).await.map_err(|e| {
+        error!("{}", e);
+        Response::Problem(Problem::new(
+            Status::InternalServerError,
+            "generation_failure",
+            "an error occured in festival/flite while generating the requested phrase",
+        ))
+    })?;
+    if !generated {
+        metrics.observe_flite(start.elapsed());
+    }
+
+    // Convert the file
+    if !converter.is_supported(phrase_package.format()) {
+        failure!(
+            converter_unavailable,
+            "requested file format is not available"
+        )
+    }
+
+    let converted = phrase_package.converted_path(cfg).exists();
+    let start = Instant::now();
+    let converted_file = match converter
+        .convert(phrase_package, phrase_package.speed, cfg)
+        .await
+    {
+        Ok(f) => f,
+        Err(_) => failure!(conversion_failure, "unable to convert file to desired format due to internal error, try again with request as wav"),
+    };
+    if !converted {
+        metrics.observe_ffmpeg(start.elapsed());
+    }
+
+    //Cache File
+    if let Err(e) = cache.used(generated_file).await {
+        error!("cache error {}", e);
+        failure!(cache_failure, "cache failure");
+    }
+
+    Ok(converted_file)
 }
 
 /// Expects a phrase package, attempts to convert it to a sound file to be returned to the user.
+/// If no format is provided, one is chosen from the `Accept` header of the request. With `share=true` an expiring
+/// link to the sound file is returned instead, which may be downloaded without an api key.
 /// Requires an authenticate user account to access. This endpoint also features strict rate limiting
 /// as generating .wav files is very resource intensive.
-#[post("/convert", data = "<phrase_package>", format = "application/json")]
+#[allow(clippy::too_many_arguments)]
+#[post(
+    "/convert?<disposition>&<share>",
+    data = "<phrase_package>",
+    format = "application/json"
+)]
 pub async fn convert(
-    mut phrase_package: Json<PhrasePackage>,
+    phrase_package: Json<PhrasePackage>,
+    disposition: Option<Disposition>,
+    share: Option<bool>,
+    file_request: FileRequest<'_>,
+    accept: Option<&Accept>,
+    links: &ShareLinks,
+    user: &User,
+    client: ClientId,
+    limiter: &RateLimiter,
     generator: &Flite,
     converter: &Converter,
     cfg: &Config,
     cache: Cache,
+    db: &Database,
+    metrics: &Metrics,
+    labels: RequestLabels<'_>,
 ) -> Result<Response, Response> {
-    // Validate PhrasePackage
-    phrase_package.validated(cfg).map_err(|e| {
-        Response::TextErr(Data {
-            data: e,
-            status: Status::BadRequest,
-        })
-    })?;
-    let phrase_package = phrase_package.into_inner();
+    convert_phrase(
+        phrase_package.into_inner(),
+        disposition,
+        share.unwrap_or_default(),
+        file_request,
+        accept,
+        links,
+        user,
+        client,
+        limiter,
+        generator,
+        converter,
+        cfg,
+        cache,
+        db,
+        metrics,
+        labels,
+    )
+    .await
+}
 
-    // Generate the phrase
-    let generated_file = generator
-        .generate(&phrase_package, cfg)
-        .await
-        .map_err(|e| {
-            error!("{}", e);
-            Response::TextErr(Data {
-                data: String::from(
-                    "an error occured in festival/flite while generating the requested phrase",
-                ),
-                status: Status::InternalServerError,
-            })
-        })?;
+/// Identical to `convert`, but with the phrase package provided as query parameters so that a phrase may be
+/// addressed by url, e.g. as the `src` of an `<audio>` element. `HEAD` requests are also answered by this route.
+#[allow(clippy::too_many_arguments)]
+#[get("/convert?<word>&<lang>&<speed>&<fmt>&<blacklist_policy>&<disposition>&<share>")]
+pub async fn convert_get(
+    word: Option<String>,
+    lang: Option<String>,
+    speed: Option<f32>,
+    fmt: Option<String>,
+    blacklist_policy: Option<BlacklistPolicy>,
+    disposition: Option<Disposition>,
+    share: Option<bool>,
+    file_request: FileRequest<'_>,
+    accept: Option<&Accept>,
+    links: &ShareLinks,
+    user: &User,
+    client: ClientId,
+    limiter: &RateLimiter,
+    generator: &Flite,
+    converter: &Converter,
+    cfg: &Config,
+    cache: Cache,
+    db: &Database,
+    metrics: &Metrics,
+    labels: RequestLabels<'_>,
+) -> Result<Response, Response> {
+    let phrase_package = match (word, lang, speed) {
+        (Some(word), Some(lang), Some(speed)) => PhrasePackage {
+            word,
+            lang,
+            speed,
+            fmt,
+            blacklist_policy,
+            bleeps: vec![],
+        },
+        (word, lang, _) => {
+            let field = if word.is_none() {
+                "word"
+            } else if lang.is_none() {
+                "lang"
+            } else {
+                "speed"
+            };
+            let mut problem = Problem::new(
+                Status::BadRequest,
+                "invalid_query",
+                format!("Query parameter ({}) is missing or invalid!", field),
+            );
+            problem.field = Some(String::from(field));
+            return Err(Response::Problem(problem));
+        }
+    };
+    convert_phrase(
+        phrase_package,
+        disposition,
+        share.unwrap_or_default(),
+        file_request,
+        accept,
+        links,
+        user,
+        client,
+        limiter,
+        generator,
+        converter,
+        cfg,
+        cache,
+        db,
+        metrics,
+        labels,
+    )
+    .await
+}
 
-    // Convert the file
-    if !converter.is_supported(&phrase_package.fmt) {
-        failure!("requested file format is not available")
+/// Validates, generates and converts a single phrase on behalf of a user, returning the resulting sound file.
+#[allow(clippy::too_many_arguments)]
+async fn convert_phrase(
+    mut phrase_package: PhrasePackage,
+    disposition: Option<Disposition>,
+    share: bool,
+    file_request: FileRequest<'_>,
+    accept: Option<&Accept>,
+    links: &ShareLinks,
+    user: &User,
+    client: ClientId,
+    limiter: &RateLimiter,
+    generator: &Flite,
+    converter: &Converter,
+    cfg: &Config,
+    cache: Cache,
+    db: &Database,
+    metrics: &Metrics,
+    labels: RequestLabels<'_>,
+) -> Result<Response, Response> {
+    // Choose a format, then validate PhrasePackage
+    negotiate_format(&mut phrase_package, accept, converter, cfg).map_err(Response::Problem)?;
+    phrase_package
+        .validated(cfg)
+        .map_err(|e| Response::Problem(invalid_phrase(e)))?;
+    labels.set(&phrase_package);
+
+    // Enforce rate limits, only phrases which are not yet cached are charged against the generation budget
+    let cached = phrase_package.converted_path(cfg).exists() as u32;
+    limiter
+        .acquire(&client, cached, 1 - cached)
+        .map_err(rate_limited)?;
+
+    let start = Instant::now();
+    let converted_file =
+        generate_and_convert(&phrase_package, generator, converter, cfg, &cache, metrics).await?;
+    db.record(&phrase_package, user, cached == 1, start.elapsed())
+        .await;
+
+    if share {
+        let file = converted_file
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy();
+        let (url, expires) = links.sign(&file);
+        let data = match serde_json::to_string(&ShareLink {
+            url: &url,
+            expires: (UNIX_EPOCH + Duration::from_secs(expires)).into(),
+        }) {
+            Ok(d) => d,
+            Err(e) => failure!(
+                serialization_failure,
+                "unable to serialize share link {}",
+                e
+            ),
+        };
+        return Ok(Response::JsonOk(Data {
+            data,
+            status: Status::Ok,
+        }));
     }
 
-    //Generate Response
-    let response = match converter.convert(
-        &phrase_package,
-        phrase_package.speed,
+    audio_file(
+        converted_file,
+        phrase_package.format(),
+        converter,
         cfg,
-    ).await {
-        Ok(f) => {
-            let resp_file = match NamedFile::open(f).await {
-                Ok(f) => f,
-                Err(e) => failure!("Unable to open processed file {}, this is an internal error", e),
-            };
+        disposition,
+        file_request,
+    )
+    .await
+}
+
+/// Serves a phrase which has already been converted, by the file name provided in the `Content-Location` header
+/// of a converted phrase, `<hash>.<fmt>`. Phrases which are not present in the cache are never generated, a 404
+/// is returned instead. Requires an authenticated user account to access, and is charged as a cached request.
+#[allow(clippy::too_many_arguments)]
+#[get("/audio/<file>?<disposition>")]
+pub async fn cached_audio(
+    file: &str,
+    disposition: Option<Disposition>,
+    file_request: FileRequest<'_>,
+    _user: &User,
+    client: ClientId,
+    limiter: &RateLimiter,
+    converter: &Converter,
+    cfg: &Config,
+) -> Result<Response, Response> {
+    let (hash, fmt) = file.rsplit_once('.').unwrap_or((file, ""));
+
+    let mut bytes: [u8; 32] = [0; 32];
+    if hex::decode_to_slice(hash, &mut bytes).is_err() {
+        reject!(
+            invalid_hash,
+            "Provided hash ({}) is not a valid sha256 hash!",
+            hash
+        );
+    }
+    if !cfg.ALLOWED_FORMATS().contains(fmt) {
+        return Err(Response::Problem(invalid_phrase(
+            ValidationError::UnsupportedFormat(fmt.to_owned()),
+        )));
+    }
+
+    limiter.acquire(&client, 1, 0).map_err(rate_limited)?;
+
+    let path = PathBuf::from(cfg.CACHE_PATH()).join(file);
+    if !path.exists() {
+        return Err(Response::Problem(
+            Problem::new(
+                Status::NotFound,
+                "audio_not_found",
+                format!("Audio ({}) has not been generated!", file),
+            )
+            .with_field("file", file),
+        ));
+    }
+
+    audio_file(path, fmt, converter, cfg, disposition, file_request).await
+}
 
-            Ok(Response::FileDownload((
+/// Opens a converted sound file to be sent to the user, with the content type of its format.
+/// Files are sent as an attachment unless the client requests otherwise. As the file name of a converted file
+/// is a hash of its content, it is used as the etag to allow clients to revalidate their copy, or request a range,
+/// and to provide a stable url from which the file may be retrieved again through `cached_audio`.
+pub(crate) async fn audio_file(
+    path: PathBuf,
+    fmt: &str,
+    converter: &Converter,
+    cfg: &Config,
+    disposition: Option<Disposition>,
+    file_request: FileRequest<'_>,
+) -> Result<Response, Response> {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let etag = format!("\"{}\"", file_name);
+    let location = format!("/api/audio/{}", file_name);
+    let meta = FileMeta {
+        filename: format!("output.{}", fmt),
+        content_type: converter
+            .mime_type(fmt)
+            .and_then(ContentType::parse_flexible)
+            .unwrap_or(ContentType::Binary),
+        disposition: disposition.unwrap_or_default(),
+        etag,
+        location,
+        max_age: cfg.CACHE_CONTROL_MAX_AGE(),
+    };
+
+    if file_request.not_modified(&meta.etag) {
+        return Ok(Response::NotModified(meta));
+    }
+
+    let mut resp_file = match NamedFile::open(path).await {
+        Ok(f) => f,
+        Err(e) => failure!(
+            file_unavailable,
+            "Unable to open processed file {}, this is an internal error",
+            e
+        ),
+    };
+
+    let range = match file_request.range(&meta.etag) {
+        Some(r) => r,
+        None => {
+            return Ok(Response::FileDownload((
                 Data {
                     data: resp_file,
                     status: Status::Ok,
                 },
-                format!("output.{}", phrase_package.fmt),
+                meta,
             )))
-        },
-        Err(_) => failure!("unable to convert file to desired format due to internal error, try again with request as wav"),
+        }
     };
 
-    //Cache File
-    if let Err(e) = cache.used(generated_file.to_path_buf()).await {
-        error!("cache error {}", e);
-        failure!("cache failure");
+    let total = match resp_file.file().metadata().await {
+        Ok(m) => m.len(),
+        Err(e) => failure!(file_unavailable, "Unable to read processed file {}", e),
+    };
+    let range = match ByteRange::parse(range, total) {
+        Ok(Some(r)) => r,
+        Ok(None) => {
+            return Ok(Response::FileDownload((
+                Data {
+                    data: resp_file,
+                    status: Status::Ok,
+                },
+                meta,
+            )))
+        }
+        Err(UnsatisfiableRange) => {
+            return Err(Response::RangeNotSatisfiable((
+                Problem::new(
+                    Status::RangeNotSatisfiable,
+                    "range_not_satisfiable",
+                    format!("Requested range ({}) lies outside of the file!", range),
+                )
+                .with_field("range", range),
+                total,
+            )))
+        }
+    };
+
+    let mut data = Vec::with_capacity(range.size() as usize);
+    let file = resp_file.file_mut();
+    if let Err(e) = file.seek(SeekFrom::Start(range.start)).await {
+        failure!(file_unavailable, "Unable to read processed file {}", e);
     }
+    if let Err(e) = file.take(range.size()).read_to_end(&mut data).await {
+        failure!(file_unavailable, "Unable to read processed file {}", e);
+    }
+
+    Ok(Response::PartialDownload((
+        Data {
+            data,
+            status: Status::PartialContent,
+        },
+        meta,
+        range,
+    )))
+}
+
+/// Expects a list of phrase packages, each of which is validated, generated and converted concurrently.
+/// Returns a zip archive containing one sound file per phrase, alongside a `manifest.json` mapping the index
+/// of each provided phrase to either its file in the archive or the reason it could not be generated.
+/// A failure on a single phrase does not fail the batch. Phrases without a format are given one chosen from the
+/// `Accept` header of the request. Requires an authenticated user account to access.
+#[allow(clippy::too_many_arguments)]
+#[post(
+    "/convert/batch",
+    data = "<phrase_packages>",
+    format = "application/json"
+)]
+pub async fn convert_batch(
+    phrase_packages: Json<Vec<PhrasePackage>>,
+    accept: Option<&Accept>,
+    user: &User,
+    client: ClientId,
+    limiter: &RateLimiter,
+    generator: &Flite,
+    converter: &Converter,
+    cfg: &Config,
+    cache: Cache,
+    db: &Database,
+    metrics: &Metrics,
+    labels: RequestLabels<'_>,
+) -> Result<Response, Response> {
+    /// The number of phrases from a single batch which may be processed at once
+    const WORKER_COUNT: usize = 4;
+
+    let phrase_packages = phrase_packages.into_inner();
+    if phrase_packages.is_empty() {
+        reject!(empty_batch, "No phrases provided!");
+    }
+    if phrase_packages.len() > cfg.BATCH_SIZE_LIMIT() {
+        reject!(
+            batch_too_large,
+            "Batch is too large! Greater than {} phrases",
+            cfg.BATCH_SIZE_LIMIT()
+        );
+    }
+
+    // Choose a format for, and validate, every PhrasePackage before any are generated
+    let mut validated: Vec<Result<(PhrasePackage, bool), Problem>> =
+        Vec::with_capacity(phrase_packages.len());
+    for mut phrase_package in phrase_packages {
+        if let Err(p) = negotiate_format(&mut phrase_package, accept, converter, cfg) {
+            validated.push(Err(p));
+            continue;
+        }
+        if let Err(e) = phrase_package.validated(cfg) {
+            validated.push(Err(invalid_phrase(e)));
+            continue;
+        }
+        labels.set(&phrase_package);
+        let cached = phrase_package.converted_path(cfg).exists();
+        validated.push(Ok((phrase_package, cached)));
+    }
+    let phrase_packages = validated;
+
+    // Enforce rate limits, only phrases which are not yet cached are charged against the generation budget.
+    // A batch may hold more phrases than a budget, so those which don't fit are rejected individually.
+    let valid = phrase_packages.iter().flatten().count() as u32;
+    let cached = phrase_packages.iter().flatten().filter(|(_, c)| *c).count() as u32;
+    let mut grant = limiter
+        .acquire_up_to(&client, cached, valid - cached)
+        .map_err(rate_limited)?;
+    let mut admitted: Vec<Result<(PhrasePackage, bool), Problem>> =
+        Vec::with_capacity(phrase_packages.len());
+    for phrase_package in phrase_packages {
+        let (phrase_package, cached) = match phrase_package {
+            Ok(p) => p,
+            Err(p) => {
+                admitted.push(Err(p));
+                continue;
+            }
+        };
+        let remaining = match cached {
+            true => &mut grant.cached,
+            false => &mut grant.generated,
+        };
+        if *remaining == 0 {
+            admitted.push(Err(Problem::new(
+                Status::TooManyRequests,
+                "rate_limited",
+                "Rate limit exceeded! This phrase was not converted, please retry it in a later request.",
+            )));
+            continue;
+        }
+        *remaining -= 1;
+        admitted.push(Ok((phrase_package, cached)));
+    }
+
+    let cache = &cache;
+    let results: Vec<Result<(PathBuf, String), Problem>> =
+        stream::iter(admitted.into_iter().enumerate())
+            .map(|(index, phrase_package)| async move {
+                let (phrase_package, cached) = phrase_package?;
+                let start = Instant::now();
+                let path = generate_and_convert(
+                    &phrase_package,
+                    generator,
+                    converter,
+                    cfg,
+                    cache,
+                    metrics,
+                )
+                .await
+                .map_err(into_problem)?;
+                db.record(&phrase_package, user, cached, start.elapsed())
+                    .await;
+                Ok((path, format!("{}.{}", index, phrase_package.format())))
+            })
+            .buffered(WORKER_COUNT)
+            .collect()
+            .await;
+
+    //Generate Archive
+    let mut archive = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+    let mut manifest = Vec::with_capacity(results.len());
+
+    for (index, result) in results.into_iter().enumerate() {
+        let (path, file) = match result {
+            Ok(r) => r,
+            Err(problem) => {
+                manifest.push(BatchManifestEntry {
+                    index,
+                    file: None,
+                    code: Some(problem.code),
+                    error: Some(problem.detail),
+                });
+                continue;
+            }
+        };
+
+        let data = match rocket::tokio::fs::read(&path).await {
+            Ok(d) => d,
+            Err(e) => failure!(
+                file_unavailable,
+                "Unable to open processed file {}, this is an internal error",
+                e
+            ),
+        };
+        if let Err(e) = archive
+            .start_file(file.as_str(), options)
+            .and_then(|_| archive.write_all(&data).map_err(ZipError::from))
+        {
+            failure!(archive_failure, "Unable to write file to archive {}", e);
+        }
+
+        manifest.push(BatchManifestEntry {
+            index,
+            file: Some(file),
+            code: None,
+            error: None,
+        });
+    }
+
+    let manifest = match serde_json::to_vec(&manifest) {
+        Ok(m) => m,
+        Err(e) => failure!(serialization_failure, "unable to serialize manifest {}", e),
+    };
+    let archive = archive
+        .start_file("manifest.json", options)
+        .and_then(|_| archive.write_all(&manifest).map_err(ZipError::from))
+        .and_then(|_| archive.finish());
+    let archive = match archive {
+        Ok(a) => a.into_inner(),
+        Err(e) => failure!(archive_failure, "Unable to generate archive {}", e),
+    };
+
+    Ok(Response::ArchiveDownload((
+        Data {
+            data: archive,
+            status: Status::Ok,
+        },
+        String::from("output.zip"),
+    )))
+}
+
+/// Returned when a request to an authenticated endpoint does not provide a valid api key.
+#[catch(401)]
+pub fn unauthorized() -> Response {
+    Response::Problem(Problem::new(
+        Status::Unauthorized,
+        "unauthorized",
+        format!(
+            "A valid api key must be provided in the {} header, or the {} query parameter!",
+            API_KEY_HEADER, API_KEY_QUERY
+        ),
+    ))
+}
+
+/// Returned when a request to an authenticated endpoint is made by a disabled user.
+#[catch(403)]
+pub fn forbidden() -> Response {
+    Response::Problem(Problem::new(
+        Status::Forbidden,
+        "forbidden",
+        "This user is not permitted to access this api!",
+    ))
+}
 
-    response
+/// Returned for any other error which is not handled by an endpoint, such as an unknown route or a malformed body.
+/// The code is derived from the status, e.g. `not_found` or `unprocessable_entity`.
+#[catch(default)]
+pub fn default_catcher(status: Status, _req: &rocket::Request) -> Response {
+    let code = status
+        .reason_lossy()
+        .to_lowercase()
+        .replace(|c: char| !c.is_ascii_alphanumeric(), "_");
+    Response::Problem(Problem::new(status, code, status.reason_lossy()))
 }
 
 #[doc(hidden)]
 #[launch]
 pub fn rocket() -> _ {
     rocket::build()
-        .mount("/", routes![index])
-        .mount("/api/", routes![convert])
+        .mount("/", routes![index, metrics::export, docs::spec, docs::page])
+        .mount("/health", routes![health::live, health::ready])
+        .mount(
+            "/api/",
+            routes![
+                convert,
+                convert_get,
+                cached_audio,
+                share::shared,
+                convert_batch,
+                langs,
+                formats,
+                jobs::create,
+                jobs::status,
+                jobs::audio,
+                admin::stats,
+                admin::export,
+                admin::cache_stats,
+                admin::cache_purge,
+                admin::cache_purge_phrase,
+                admin::cache_pin,
+                admin::cache_unpin,
+                admin::cache_purge_all,
+                admin::cache_enforce
+            ],
+        )
+        .register("/", catchers![unauthorized, forbidden, default_catcher])
         .attach(Config::fairing())
+        .attach(Metrics::fairing())
+        .attach(Database::fairing())
         .attach(Flite::fairing())
         .attach(Converter::fairing(vec![Box::new(
             Ffmpeg::new().expect("a valid ffmpeg instance"),
         )]))
+        .attach(Docs::fairing())
         .attach(Cache::fairing())
+        .attach(RateLimiter::fairing())
+        .attach(ShareLinks::fairing())
+        .attach(Jobs::fairing())
 }
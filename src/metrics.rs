@@ -0,0 +1,224 @@
+//! Prometheus metrics for the api, exported in the text format from the `/metrics` endpoint.
+//! Handlers label requests with the language and format of the phrase once it has been validated, and every
+//! request is counted when its response is sent.
+
+use std::{convert::Infallible, sync::Mutex, time::Duration};
+
+use cache_manager::Cache;
+use macros::failure;
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge, Opts, Registry,
+    TextEncoder,
+};
+use response::{Data, Response};
+use rocket::{
+    fairing::AdHoc,
+    http::Status,
+    request::{self, FromRequest},
+    Request,
+};
+use utils::phrase_package::PhrasePackage;
+
+/// The label used when a request has no phrase, or the phrase was invalid.
+const NO_LABEL: &str = "none";
+
+/// The label used when a request contains phrases with differing languages or formats.
+const MIXED_LABEL: &str = "mixed";
+
+/// The metrics collected by the api.
+#[derive(Debug, Clone)]
+pub struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    flite_duration: Histogram,
+    ffmpeg_duration: Histogram,
+    cache_hits: IntCounter,
+    cache_misses: IntCounter,
+    cache_size: IntGauge,
+    cache_evictions: IntCounter,
+}
+
+impl Metrics {
+    pub fn new() -> Result<Self, prometheus::Error> {
+        let registry = Registry::new_custom(Some(String::from("festival")), None)?;
+
+        let requests = IntCounterVec::new(
+            Opts::new("requests_total", "Requests made to the api"),
+            &["endpoint", "status", "lang", "fmt"],
+        )?;
+        let flite_duration = Histogram::with_opts(HistogramOpts::new(
+            "flite_duration_seconds",
+            "Time taken by flite to generate a phrase",
+        ))?;
+        let ffmpeg_duration = Histogram::with_opts(HistogramOpts::new(
+            "ffmpeg_duration_seconds",
+            "Time taken by ffmpeg to convert a phrase",
+        ))?;
+        let cache_hits = IntCounter::new(
+            "cache_hits_total",
+            "Phrases served from the cache without generation",
+        )?;
+        let cache_misses = IntCounter::new(
+            "cache_misses_total",
+            "Phrases which had to be generated or converted",
+        )?;
+        let cache_size = IntGauge::new("cache_size_bytes", "Current size of the cache")?;
+        let cache_evictions = IntCounter::new(
+            "cache_evictions_total",
+            "Files removed to keep the cache under the maximum allowed size",
+        )?;
+
+        registry.register(Box::new(requests.clone()))?;
+        registry.register(Box::new(flite_duration.clone()))?;
+        registry.register(Box::new(ffmpeg_duration.clone()))?;
+        registry.register(Box::new(cache_hits.clone()))?;
+        registry.register(Box::new(cache_misses.clone()))?;
+        registry.register(Box::new(cache_size.clone()))?;
+        registry.register(Box::new(cache_evictions.clone()))?;
+
+        Ok(Metrics {
+            registry,
+            requests,
+            flite_duration,
+            ffmpeg_duration,
+            cache_hits,
+            cache_misses,
+            cache_size,
+            cache_evictions,
+        })
+    }
+
+    /// Generate an adhoc fairing which can be bound to a launching rocket.
+    /// This also attaches a fairing which counts every response sent by the api.
+    pub fn fairing() -> AdHoc {
+        AdHoc::on_ignite("Metrics", |rocket| {
+            Box::pin(async move {
+                let metrics = Metrics::new()
+                    .unwrap_or_else(|e| panic!("Unable to create metrics due to {}", e));
+
+                rocket
+                    .manage(metrics)
+                    .attach(AdHoc::on_response("Request Metrics", |req, res| {
+                        Box::pin(async move {
+                            let metrics = req
+                                .rocket()
+                                .state::<Metrics>()
+                                .expect("metrics fairing attached");
+                            metrics.record_response(req, res.status());
+                        })
+                    }))
+            })
+        })
+    }
+
+    /// Count a response, labelled with the route that handled it and the phrase it contained.
+    fn record_response(&self, req: &Request<'_>, status: Status) {
+        let endpoint = req
+            .route()
+            .and_then(|r| r.name.as_deref())
+            .unwrap_or(NO_LABEL);
+        let labels = req
+            .local_cache(|| Mutex::new(Labels::default()))
+            .lock()
+            .expect("metric labels lock poisoned");
+
+        self.requests
+            .with_label_values(&[
+                endpoint,
+                status.code.to_string().as_str(),
+                labels.lang.as_deref().unwrap_or(NO_LABEL),
+                labels.fmt.as_deref().unwrap_or(NO_LABEL),
+            ])
+            .inc();
+    }
+
+    pub fn observe_flite(&self, duration: Duration) {
+        self.flite_duration.observe(duration.as_secs_f64());
+    }
+
+    pub fn observe_ffmpeg(&self, duration: Duration) {
+        self.ffmpeg_duration.observe(duration.as_secs_f64());
+    }
+
+    /// Count whether a phrase was served from the cache.
+    pub fn cache_lookup(&self, hit: bool) {
+        match hit {
+            true => self.cache_hits.inc(),
+            false => self.cache_misses.inc(),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for &'r Metrics {
+    type Error = Infallible;
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Infallible> {
+        let state = req
+            .rocket()
+            .state::<Metrics>()
+            .expect("metrics fairing attached");
+        request::Outcome::Success(state)
+    }
+}
+
+/// The language and format of the phrases in a request.
+#[derive(Debug, Default)]
+struct Labels {
+    lang: Option<String>,
+    fmt: Option<String>,
+}
+
+/// Allows a handler to label the current request with the phrase it contains, for the purposes of metrics.
+/// Phrases should only be labelled once validated, so arbitrary user input does not end up as a label.
+pub struct RequestLabels<'r>(&'r Mutex<Labels>);
+
+impl RequestLabels<'_> {
+    pub fn set(&self, phrase_package: &PhrasePackage) {
+        fn merge(label: &mut Option<String>, value: &str) {
+            match label {
+                Some(l) if l != value => *l = String::from(MIXED_LABEL),
+                Some(_) => {}
+                None => *label = Some(value.to_owned()),
+            }
+        }
+
+        let mut labels = self.0.lock().expect("metric labels lock poisoned");
+        merge(&mut labels.lang, &phrase_package.lang);
+        merge(&mut labels.fmt, phrase_package.format());
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RequestLabels<'r> {
+    type Error = Infallible;
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Infallible> {
+        request::Outcome::Success(RequestLabels(
+            req.local_cache(|| Mutex::new(Labels::default())),
+        ))
+    }
+}
+
+/// Exports every metric collected by the api in the prometheus text format.
+#[get("/metrics")]
+pub async fn export(metrics: &Metrics, cache: Cache) -> Result<Response, Response> {
+    match cache.stats().await {
+        Ok(stats) => {
+            metrics.cache_size.set(stats.current_size_bytes as i64);
+            let evictions = stats.evicted_files_total;
+            metrics
+                .cache_evictions
+                .inc_by(evictions.saturating_sub(metrics.cache_evictions.get()));
+        }
+        Err(e) => error!("unable to collect cache stats {}", e),
+    }
+
+    let mut data = Vec::new();
+    if let Err(e) = TextEncoder::new().encode(&metrics.registry.gather(), &mut data) {
+        failure!(metrics_failure, "unable to encode metrics {}", e);
+    }
+
+    Ok(Response::TextOk(Data {
+        data: String::from_utf8_lossy(&data).into_owned(),
+        status: Status::Ok,
+    }))
+}
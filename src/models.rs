@@ -1,24 +1,159 @@
 //! Various objects, including database objects, for the api.
-use chrono::Utc;
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use rocket::serde::{Deserialize, Serialize};
+
+/// An output format which is both allowed by the configuration, and supported by a converter.
+/// This is returned from the `/api/formats` endpoint.
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct FormatCapability<'a> {
+    pub format: &'a str,
+    pub converter: &'a str,
+    pub mime_type: &'a str,
+}
+
+/// Describes the outcome of a single phrase within a batch conversion, this is collected into the
+/// `manifest.json` of the archive returned from the `/api/convert/batch` endpoint.
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct BatchManifestEntry {
+    pub index: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    /// The stable code of the error, as found on the problem returned from `/api/convert`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A phrase in the cache, which is purged, pinned or unpinned through the `/api/admin/cache` endpoints.
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct CachedPhrase {
+    pub word: String,
+    pub lang: String,
+}
+
+/// The outcome of pinning or unpinning a phrase in the cache, returned from the `/api/admin/cache/pin` endpoint.
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct PinReport {
+    /// The number of hashes, out of every speed and format of the phrase, whose pin changed.
+    pub hashes_changed: usize,
+}
+
+/// Returned when a job is created, the id may be used to poll the status of the job.
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct JobCreated<'a> {
+    pub id: &'a str,
+}
+
+/// The current status of a job, as returned from the `/api/jobs/<id>` endpoint.
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct JobReport<'a> {
+    pub id: &'a str,
+    pub status: &'a str,
+    /// The stable code of the error, only present when the job has failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<&'a str>,
+}
+
+/// A link from which a converted phrase may be downloaded without an api key, returned from `/api/convert`
+/// when `share=true` is requested.
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ShareLink<'a> {
+    pub url: &'a str,
+    pub expires: DateTime<Utc>,
+}
 
 /// A request to generate a .wav file from text from a user that has been stored in the db.
 /// This is a return object from the reqs table of the database.
+#[derive(Debug, Clone)]
 pub struct GenerationRequest {
     pub id: i32,
     pub usr_id: i32,
-    pub crt: chrono::DateTime<Utc>,
+    pub crt: DateTime<Utc>,
     pub word: String,
     pub lang: String,
     pub speed: f32,
     pub fmt: String,
+    /// Whether the phrase was served from the cache, rather than being generated.
+    pub cached: bool,
+    /// How long the phrase took to generate and convert, in milliseconds.
+    pub latency_ms: u32,
 }
 
 /// A request to generate a .wav file from text for a user, to be stored in the db.
 /// This is an insertion object for the reqs table of the database.
+#[derive(Debug, Clone)]
 pub struct NewGenerationRequest {
     pub usr_id: i32,
     pub word: String,
     pub lang: String,
     pub speed: f32,
     pub fmt: String,
+    /// Whether the phrase was served from the cache, rather than being generated.
+    pub cached: bool,
+    /// How long the phrase took to generate and convert, in milliseconds.
+    pub latency_ms: u32,
+}
+
+/// The number of times a phrase was requested within a window, as reported from the `/api/admin/stats` endpoint.
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct PhraseCount {
+    pub word: String,
+    pub lang: String,
+    pub count: usize,
+}
+
+/// Percentiles of the time taken to serve a phrase, in milliseconds.
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct LatencySummary {
+    pub p50_ms: u32,
+    pub p95_ms: u32,
+}
+
+/// A summary of the usage of the api within a window, as returned from the `/api/admin/stats` endpoint.
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct UsageStats {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub total: usize,
+    /// The fraction of requests which were served from the cache, 0 if there were no requests.
+    pub cache_hit_ratio: f64,
+    /// Only present if there were requests within the window.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency: Option<LatencySummary>,
+    pub top_phrases: Vec<PhraseCount>,
+    pub langs: BTreeMap<String, usize>,
+    pub formats: BTreeMap<String, usize>,
+}
+
+/// The outcome of a single readiness check.
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct HealthCheck {
+    pub ok: bool,
+    /// Only present if the check failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Whether the api is ready to serve requests, as returned from the `/health/ready` endpoint.
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Readiness {
+    pub ready: bool,
+    pub checks: BTreeMap<&'static str, HealthCheck>,
 }
@@ -0,0 +1,192 @@
+//! Expiring share links, which allow a converted phrase to be downloaded without an api key.
+//! A link names a file in the cache and the time at which it expires, signed with HMAC-SHA256 so that
+//! neither may be altered by the holder of the link.
+
+use std::{
+    convert::Infallible,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use config::Config;
+use converter::Converter;
+use hmac::{Hmac, Mac, NewMac};
+use rate_limiter::{ClientId, RateLimiter};
+use response::{Disposition, FileRequest, Problem, Response};
+use rocket::{
+    fairing::AdHoc,
+    http::Status,
+    request::{self, FromRequest},
+    Request,
+};
+use sha2::Sha256;
+use utils::generate_random_alphanumeric;
+
+/// The length of the secret generated when none is configured.
+const GENERATED_SECRET_LENGTH: usize = 64;
+
+/// The reasons a share link may be refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShareError {
+    /// The signature does not match the file and expiry of the link, it has been altered or was never valid.
+    InvalidSignature,
+    Expired,
+}
+
+impl std::fmt::Display for ShareError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            ShareError::InvalidSignature => write!(f, "Share link is not valid!"),
+            ShareError::Expired => write!(f, "Share link has expired!"),
+        }
+    }
+}
+
+impl std::error::Error for ShareError {}
+
+/// Creates and verifies share links.
+#[derive(Debug, Clone)]
+pub struct ShareLinks {
+    secret: Vec<u8>,
+    ttl: Duration,
+}
+
+impl ShareLinks {
+    pub fn new<S: Into<Vec<u8>>>(secret: S, ttl: Duration) -> Self {
+        ShareLinks {
+            secret: secret.into(),
+            ttl,
+        }
+    }
+
+    pub fn fairing() -> AdHoc {
+        AdHoc::on_ignite("Share Links", |rocket| {
+            Box::pin(async move {
+                let cfg = rocket
+                    .state::<Config>()
+                    .expect("Configuration Fairing Not Attached!");
+
+                let secret = match cfg.SHARE_LINK_SECRET() {
+                    "" => {
+                        warn!("SHARE_LINK_SECRET is not set, share links will be invalidated on restart");
+                        generate_random_alphanumeric(GENERATED_SECRET_LENGTH)
+                    }
+                    s => s.to_owned(),
+                };
+                let links =
+                    ShareLinks::new(secret, Duration::from_secs(cfg.SHARE_LINK_TTL() as u64));
+
+                rocket.manage(links)
+            })
+        })
+    }
+
+    fn mac(&self, file: &str, expires: u64) -> Hmac<Sha256> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.secret).expect("hmac accepts keys of any length");
+        mac.update(file.as_bytes());
+        mac.update(b"\n");
+        mac.update(expires.to_string().as_bytes());
+        mac
+    }
+
+    /// Create a link to a file in the cache, returning the link alongside the unix time at which it expires.
+    pub fn sign(&self, file: &str) -> (String, u64) {
+        let expires = (SystemTime::now() + self.ttl)
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let signature = hex::encode(self.mac(file, expires).finalize().into_bytes());
+        (
+            format!(
+                "/api/share/{}?expires={}&signature={}",
+                file, expires, signature
+            ),
+            expires,
+        )
+    }
+
+    /// Check that a link was created by this api, and has not yet expired.
+    pub fn verify(&self, file: &str, expires: u64, signature: &str) -> Result<(), ShareError> {
+        let signature = hex::decode(signature).map_err(|_| ShareError::InvalidSignature)?;
+        self.mac(file, expires)
+            .verify(&signature)
+            .map_err(|_| ShareError::InvalidSignature)?;
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        if now >= expires {
+            return Err(ShareError::Expired);
+        }
+        Ok(())
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for &'r ShareLinks {
+    type Error = Infallible;
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Infallible> {
+        let state = req
+            .rocket()
+            .state::<ShareLinks>()
+            .expect("share links fairing attached");
+        request::Outcome::Success(state)
+    }
+}
+
+/// Downloads a converted phrase through a share link, as returned by `convert` with `share=true`.
+/// No api key is required, but the link must be unaltered and unexpired. Requests are charged as cached requests.
+#[allow(clippy::too_many_arguments)]
+#[get("/share/<file>?<expires>&<signature>&<disposition>")]
+pub async fn shared(
+    file: &str,
+    expires: Option<u64>,
+    signature: Option<&str>,
+    disposition: Option<Disposition>,
+    file_request: FileRequest<'_>,
+    links: &ShareLinks,
+    client: ClientId,
+    limiter: &RateLimiter,
+    converter: &Converter,
+    cfg: &Config,
+) -> Result<Response, Response> {
+    let result = match (expires, signature) {
+        (Some(expires), Some(signature)) => links.verify(file, expires, signature),
+        _ => Err(ShareError::InvalidSignature),
+    };
+    if let Err(e) = result {
+        let code = match e {
+            ShareError::InvalidSignature => "invalid_signature",
+            ShareError::Expired => "link_expired",
+        };
+        return Err(Response::Problem(Problem::new(
+            Status::Forbidden,
+            code,
+            e.to_string(),
+        )));
+    }
+
+    limiter
+        .acquire(&client, 1, 0)
+        .map_err(crate::rate_limited)?;
+
+    let path = PathBuf::from(cfg.CACHE_PATH()).join(file);
+    if !path.exists() {
+        return Err(Response::Problem(
+            Problem::new(
+                Status::NotFound,
+                "audio_not_found",
+                format!("Audio ({}) is no longer available!", file),
+            )
+            .with_field("file", file),
+        ));
+    }
+
+    let fmt = file
+        .rsplit_once('.')
+        .map(|(_, fmt)| fmt)
+        .unwrap_or_default();
+    crate::audio_file(path, fmt, converter, cfg, disposition, file_request).await
+}
@@ -0,0 +1,108 @@
+use chrono::{Duration, Utc};
+use config::{PathType, API_KEY_HEADER};
+use festival_api::database::Database;
+use festival_api::models::NewGenerationRequest;
+use festival_api::rocket;
+use rocket::http::{ContentType, Header, Status};
+use rocket::local::blocking::Client;
+use rocket::uri;
+use std::path::PathBuf;
+use utils::generate_random_alphanumeric;
+use utils::test_utils::{AlteredToml, TEST_API_KEY};
+
+/// Test that requests can be stored and read back from the database
+#[rocket::async_test]
+async fn insert_and_read_requests() {
+    let db = Database::open(":memory:").expect("a valid database");
+
+    for word in ["hello", "world"] {
+        db.insert_request(NewGenerationRequest {
+            usr_id: 3,
+            word: String::from(word),
+            lang: String::from("en"),
+            speed: 1.5,
+            fmt: String::from("mp3"),
+            cached: false,
+            latency_ms: 250,
+        })
+        .await
+        .expect("a successful insert");
+    }
+
+    let reqs = db
+        .requests_between(Utc::now() - Duration::hours(1), Utc::now())
+        .await
+        .expect("a successful read");
+    assert_eq!(reqs.len(), 2);
+    assert_eq!(reqs[0].word, "hello");
+    assert_eq!(reqs[1].word, "world");
+    assert_eq!(reqs[0].usr_id, 3);
+    assert_eq!(reqs[0].lang, "en");
+    assert!((reqs[0].speed - 1.5).abs() < f32::EPSILON);
+    assert_eq!(reqs[0].fmt, "mp3");
+    assert!(!reqs[0].cached);
+    assert_eq!(reqs[0].latency_ms, 250);
+
+    //Requests outside of the window are excluded
+    let reqs = db
+        .requests_between(
+            Utc::now() - Duration::hours(2),
+            Utc::now() - Duration::hours(1),
+        )
+        .await
+        .expect("a successful read");
+    assert!(reqs.is_empty());
+}
+
+/// Test that migrations are only applied once, so an existing database can be reopened
+#[test]
+fn reopen_database() {
+    let path = std::env::temp_dir().join(format!("{}.sqlite", generate_random_alphanumeric(16)));
+
+    Database::open(&path).expect("a new database");
+    Database::open(&path).expect("an existing database");
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+/// Test that every successful conversion is recorded against the user that requested it
+#[test]
+fn conversion_recorded() {
+    let path = std::env::temp_dir().join(format!("{}.sqlite", generate_random_alphanumeric(16)));
+    let _u = AlteredToml::with_test_user(PathBuf::from("./config"));
+    let _t = AlteredToml::new(
+        "DATABASE_PATH = \"./database.sqlite\"",
+        &format!("DATABASE_PATH = \"{}\"", path.to_string_lossy()),
+        PathType::General,
+        PathBuf::from("./config"),
+    );
+
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+    let response = client
+        .post(uri!("/api/convert"))
+        .header(ContentType::new("application", "json"))
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+        .body(
+            "{
+            \"word\": \"The University of Auckland\",
+            \"lang\": \"en\",
+            \"speed\": 1.0,
+            \"fmt\": \"wav\"
+        }",
+        )
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let db = Database::open(&path).expect("a valid database");
+    let reqs = rocket::tokio::runtime::Runtime::new()
+        .unwrap()
+        .block_on(db.requests_between(Utc::now() - Duration::hours(1), Utc::now()))
+        .expect("a successful read");
+
+    assert_eq!(reqs.len(), 1);
+    assert_eq!(reqs[0].usr_id, 0);
+    assert_eq!(reqs[0].word, "The University of Auckland");
+    assert_eq!(reqs[0].fmt, "wav");
+
+    std::fs::remove_file(&path).unwrap();
+}
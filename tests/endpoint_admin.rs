@@ -0,0 +1,372 @@
+use chrono::{Duration, Utc};
+use config::{PathType, API_KEY_HEADER};
+use festival_api::admin::usage_stats;
+use festival_api::models::GenerationRequest;
+use festival_api::rocket;
+use rocket::http::{ContentType, Header, Status};
+use rocket::local::blocking::Client;
+use rocket::uri;
+use std::path::PathBuf;
+use utils::generate_random_alphanumeric;
+use utils::phrase_package::PhrasePackage;
+use utils::test_utils::{AlteredToml, TEST_ADMIN_KEY, TEST_API_KEY};
+
+fn request(word: &str, lang: &str, fmt: &str, cached: bool, latency_ms: u32) -> GenerationRequest {
+    GenerationRequest {
+        id: 0,
+        usr_id: 0,
+        crt: Utc::now(),
+        word: String::from(word),
+        lang: String::from(lang),
+        speed: 1.0,
+        fmt: String::from(fmt),
+        cached,
+        latency_ms,
+    }
+}
+
+/// Test that generation history is correctly aggregated into usage stats
+#[test]
+fn aggregate_usage_stats() {
+    let mut reqs = vec![
+        request("hello", "en", "mp3", false, 100),
+        request("hello", "en", "wav", true, 5),
+        request("hello", "mi", "mp3", false, 300),
+        request("world", "en", "mp3", true, 10),
+    ];
+    for i in 0..16 {
+        reqs.push(request(&format!("word{}", i), "en", "wav", false, 200));
+    }
+
+    let stats = usage_stats(Utc::now() - Duration::hours(1), Utc::now(), &reqs);
+    assert_eq!(stats.total, 20);
+    assert!((stats.cache_hit_ratio - 0.1).abs() < f64::EPSILON);
+
+    let latency = stats.latency.expect("a latency summary");
+    assert_eq!(latency.p50_ms, 200);
+    assert_eq!(latency.p95_ms, 200);
+
+    assert_eq!(stats.top_phrases.len(), 10);
+    assert_eq!(stats.top_phrases[0].word, "hello");
+    assert_eq!(stats.top_phrases[0].lang, "en");
+    assert_eq!(stats.top_phrases[0].count, 2);
+
+    assert_eq!(stats.langs["en"], 19);
+    assert_eq!(stats.langs["mi"], 1);
+    assert_eq!(stats.formats["mp3"], 3);
+    assert_eq!(stats.formats["wav"], 17);
+
+    let empty = usage_stats(Utc::now() - Duration::hours(1), Utc::now(), &[]);
+    assert_eq!(empty.total, 0);
+    assert!(empty.latency.is_none());
+    assert!(empty.top_phrases.is_empty());
+}
+
+/// Test that the administrative endpoints may only be accessed by administrators
+#[test]
+fn stats_requires_admin() {
+    let _u = AlteredToml::with_test_user(PathBuf::from("./config"));
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+
+    let response = client.get(uri!("/api/admin/stats")).dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    let response = client
+        .get(uri!("/api/admin/stats"))
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+
+    let response = client
+        .get(uri!("/api/admin/stats/export"))
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+}
+
+/// Test that conversions are reported in the usage stats and history export
+#[test]
+fn stats_report_conversions() {
+    let path = std::env::temp_dir().join(format!("{}.sqlite", generate_random_alphanumeric(16)));
+    let _u = AlteredToml::with_test_user(PathBuf::from("./config"));
+    let _a = AlteredToml::with_test_admin(PathBuf::from("./config"));
+    let _t = AlteredToml::new(
+        "DATABASE_PATH = \"./database.sqlite\"",
+        &format!("DATABASE_PATH = \"{}\"", path.to_string_lossy()),
+        PathType::General,
+        PathBuf::from("./config"),
+    );
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+
+    //The second conversion of the same phrase is served from the cache
+    let word = generate_random_alphanumeric(12);
+    for _ in 0..2 {
+        let response = client
+            .post(uri!("/api/convert"))
+            .header(ContentType::new("application", "json"))
+            .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+            .body(format!(
+                "{{\"word\": \"{}\", \"lang\": \"en\", \"speed\": 1.0, \"fmt\": \"wav\"}}",
+                word
+            ))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    let response = client
+        .get(uri!("/api/admin/stats?hours=1"))
+        .header(Header::new(API_KEY_HEADER, TEST_ADMIN_KEY))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::JSON));
+
+    let body: serde_json::Value =
+        serde_json::from_str(&response.into_string().unwrap()).expect("a valid json response");
+    assert_eq!(body["total"], 2);
+    assert_eq!(body["cache_hit_ratio"], 0.5);
+    assert_eq!(body["langs"]["en"], 2);
+    assert_eq!(body["formats"]["wav"], 2);
+    assert_eq!(body["top_phrases"][0]["word"], word.as_str());
+    assert_eq!(body["top_phrases"][0]["count"], 2);
+
+    let response = client
+        .get(uri!("/api/admin/stats/export?hours=1"))
+        .header(Header::new(API_KEY_HEADER, TEST_ADMIN_KEY))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::CSV));
+    assert_eq!(
+        response.headers().get_one("content-disposition").unwrap(),
+        "attachment; filename=\"history.csv\""
+    );
+
+    let body = response.into_string().unwrap();
+    let lines: Vec<&str> = body.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(
+        lines[0],
+        "id,usr_id,crt,word,lang,speed,fmt,cached,latency_ms"
+    );
+    assert!(lines[1].contains(&word));
+    assert!(lines[2].contains(",true,"));
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+/// Test that invalid windows are rejected
+#[test]
+fn stats_invalid_window() {
+    let _a = AlteredToml::with_test_admin(PathBuf::from("./config"));
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+
+    for hours in ["0", "100000"] {
+        let response = client
+            .get(format!("/api/admin/stats?hours={}", hours))
+            .header(Header::new(API_KEY_HEADER, TEST_ADMIN_KEY))
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+}
+
+/// Test that the cache may be inspected, and phrases purged from it
+#[test]
+fn cache_admin() {
+    let _u = AlteredToml::with_test_user(PathBuf::from("./config"));
+    let _a = AlteredToml::with_test_admin(PathBuf::from("./config"));
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+
+    let phrase_package = PhrasePackage {
+        word: generate_random_alphanumeric(12),
+        lang: String::from("en"),
+        speed: 1.0,
+        fmt: Some(String::from("mp3")),
+        blacklist_policy: None,
+        bleeps: vec![],
+    };
+    //Request the phrase twice, so it is the most used item in the cache
+    for _ in 0..2 {
+        let response = client
+            .post(uri!("/api/convert"))
+            .header(ContentType::new("application", "json"))
+            .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+            .body(format!(
+                "{{\"word\": \"{}\", \"lang\": \"en\", \"speed\": 1.0, \"fmt\": \"mp3\"}}",
+                phrase_package.word
+            ))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    let hash = phrase_package.filename_stem_basespeed();
+    let generated = PathBuf::from("./cache").join(format!("{}.wav", hash));
+    assert!(generated.exists());
+
+    let response = client
+        .get(uri!("/api/admin/cache"))
+        .header(Header::new(API_KEY_HEADER, TEST_ADMIN_KEY))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let body: serde_json::Value =
+        serde_json::from_str(&response.into_string().unwrap()).expect("a valid json response");
+    assert!(body["entries"].as_u64().unwrap() >= 1);
+    assert!(body["current_size_bytes"].as_u64().unwrap() > 0);
+    assert_eq!(body["max_allowed_size_bytes"], 20_000_000_000u64);
+    assert_eq!(body["most_used"][0]["hash"], hash.as_str());
+    assert_eq!(body["most_used"][0]["uses"], 2);
+
+    //Purging a phrase removes the generated file, and every file converted from it
+    let converted = PathBuf::from("./cache").join(format!(
+        "{}.mp3",
+        phrase_package.filename_stem_properspeed()
+    ));
+    assert!(converted.exists());
+    let response = client
+        .post(uri!("/api/admin/cache/purge"))
+        .header(ContentType::new("application", "json"))
+        .header(Header::new(API_KEY_HEADER, TEST_ADMIN_KEY))
+        .body(format!(
+            "{{\"word\": \"{}\", \"lang\": \"en\"}}",
+            phrase_package.word
+        ))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let body: serde_json::Value =
+        serde_json::from_str(&response.into_string().unwrap()).expect("a valid json response");
+    assert_eq!(body["files_removed"], 2);
+    assert!(!generated.exists());
+    assert!(!converted.exists());
+
+    let response = client
+        .delete(format!("/api/admin/cache/{}", hash))
+        .header(Header::new(API_KEY_HEADER, TEST_ADMIN_KEY))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+
+    let response = client
+        .delete(uri!("/api/admin/cache/not-a-hash"))
+        .header(Header::new(API_KEY_HEADER, TEST_ADMIN_KEY))
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+
+    //The cache is well under the maximum size, so nothing should be trimmed
+    let response = client
+        .post(uri!("/api/admin/cache/enforce"))
+        .header(Header::new(API_KEY_HEADER, TEST_ADMIN_KEY))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let body: serde_json::Value =
+        serde_json::from_str(&response.into_string().unwrap()).expect("a valid json response");
+    assert_eq!(body["files_removed"], 0);
+
+    //Purging everything removes the converted file as well
+    let response = client
+        .post(uri!("/api/convert"))
+        .header(ContentType::new("application", "json"))
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+        .body(format!(
+            "{{\"word\": \"{}\", \"lang\": \"en\", \"speed\": 1.0, \"fmt\": \"mp3\"}}",
+            phrase_package.word
+        ))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert!(converted.exists());
+    let response = client
+        .delete(uri!("/api/admin/cache"))
+        .header(Header::new(API_KEY_HEADER, TEST_ADMIN_KEY))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert!(!converted.exists());
+    assert!(PathBuf::from("./cache/.gitkeep").exists());
+
+    let response = client
+        .delete(uri!("/api/admin/cache"))
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+}
+
+/// Test that pinned phrases survive a purge of the entire cache, until they are unpinned
+#[test]
+fn cache_pinning() {
+    let _u = AlteredToml::with_test_user(PathBuf::from("./config"));
+    let _a = AlteredToml::with_test_admin(PathBuf::from("./config"));
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+
+    let phrase_package = PhrasePackage {
+        word: generate_random_alphanumeric(12),
+        lang: String::from("en"),
+        speed: 1.0,
+        fmt: Some(String::from("mp3")),
+        blacklist_policy: None,
+        bleeps: vec![],
+    };
+    let body = format!(
+        "{{\"word\": \"{}\", \"lang\": \"en\", \"speed\": 1.0, \"fmt\": \"mp3\"}}",
+        phrase_package.word
+    );
+    let phrase = format!(
+        "{{\"word\": \"{}\", \"lang\": \"en\"}}",
+        phrase_package.word
+    );
+    let response = client
+        .post(uri!("/api/convert"))
+        .header(ContentType::new("application", "json"))
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+        .body(&body)
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let generated =
+        PathBuf::from("./cache").join(format!("{}.wav", phrase_package.filename_stem_basespeed()));
+    let converted = PathBuf::from("./cache").join(format!(
+        "{}.mp3",
+        phrase_package.filename_stem_properspeed()
+    ));
+
+    let response = client
+        .put(uri!("/api/admin/cache/pin"))
+        .header(ContentType::new("application", "json"))
+        .header(Header::new(API_KEY_HEADER, TEST_ADMIN_KEY))
+        .body(&phrase)
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let report: serde_json::Value =
+        serde_json::from_str(&response.into_string().unwrap()).expect("a valid json response");
+    assert!(report["hashes_changed"].as_u64().unwrap() >= 2);
+
+    let response = client
+        .get(uri!("/api/admin/cache"))
+        .header(Header::new(API_KEY_HEADER, TEST_ADMIN_KEY))
+        .dispatch();
+    let stats: serde_json::Value =
+        serde_json::from_str(&response.into_string().unwrap()).expect("a valid json response");
+    assert_eq!(stats["pinned"], report["hashes_changed"]);
+
+    //Pinned files are kept when the entire cache is purged
+    let response = client
+        .delete(uri!("/api/admin/cache"))
+        .header(Header::new(API_KEY_HEADER, TEST_ADMIN_KEY))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert!(generated.exists());
+    assert!(converted.exists());
+
+    let response = client
+        .delete(uri!("/api/admin/cache/pin"))
+        .header(ContentType::new("application", "json"))
+        .header(Header::new(API_KEY_HEADER, TEST_ADMIN_KEY))
+        .body(&phrase)
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let body: serde_json::Value =
+        serde_json::from_str(&response.into_string().unwrap()).expect("a valid json response");
+    assert_eq!(body["hashes_changed"], report["hashes_changed"]);
+
+    let response = client
+        .delete(uri!("/api/admin/cache"))
+        .header(Header::new(API_KEY_HEADER, TEST_ADMIN_KEY))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert!(!generated.exists());
+    assert!(!converted.exists());
+}
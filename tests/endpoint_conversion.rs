@@ -1,14 +1,29 @@
-use config::{Config, PathType};
+use config::{Config, PathType, API_KEY_HEADER, API_KEY_QUERY};
 use festival_api::rocket;
-use rocket::http::{ContentType, Status};
-use rocket::local::blocking::Client;
+use rocket::http::{ContentType, Header, Status};
+use rocket::local::blocking::{Client, LocalResponse};
 use rocket::uri;
+use std::io::{Cursor, Read};
 use std::path::PathBuf;
-use utils::test_utils::AlteredToml;
+use utils::generate_random_alphanumeric;
+use utils::test_utils::{AlteredToml, TEST_API_KEY};
+
+/// Parse an error response, checking that it is a problem with the expected code
+fn problem(response: LocalResponse, code: &str) -> serde_json::Value {
+    assert_eq!(
+        response.content_type(),
+        Some(ContentType::new("application", "problem+json"))
+    );
+    let problem: serde_json::Value =
+        serde_json::from_str(&response.into_string().unwrap()).expect("a valid problem");
+    assert_eq!(problem["code"], code);
+    problem
+}
 
 /// Test that the word blacklist works correctly
 #[test]
 fn blacklist_filter() {
+    let _u = AlteredToml::with_test_user(PathBuf::from("./config"));
     let replace_search = "BLACKLISTED_PHRASES = []";
     let replace_data = "BLACKLISTED_PHRASES = [\"test\", \" things \", \" stuff \"]";
     let _t = AlteredToml::new(
@@ -32,14 +47,18 @@ fn blacklist_filter() {
     let response = test_client
         .post(uri!("/api/convert"))
         .header(ContentType::new("application", "json"))
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
         .body(&body)
         .dispatch();
 
     assert_eq!(response.status(), Status::BadRequest);
+    let err = problem(response, "blacklisted_phrase");
     assert_eq!(
-        response.into_string().unwrap(),
+        err["detail"],
         "Blacklisted word! Phrase (test) is not allowed!"
     );
+    assert_eq!(err["field"], "word");
+    assert_eq!(err["value"], "test");
 
     //Test no spaces works
     let body = "{
@@ -52,14 +71,18 @@ fn blacklist_filter() {
     let response = test_client
         .post(uri!("/api/convert"))
         .header(ContentType::new("application", "json"))
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
         .body(&body)
         .dispatch();
 
     assert_eq!(response.status(), Status::BadRequest);
+    let err = problem(response, "blacklisted_phrase");
     assert_eq!(
-        response.into_string().unwrap(),
+        err["detail"],
         "Blacklisted word! Phrase (test) is not allowed!"
     );
+    assert_eq!(err["field"], "word");
+    assert_eq!(err["value"], "test");
 
     //Check that no spaces works
     let body = "{
@@ -72,14 +95,18 @@ fn blacklist_filter() {
     let response = test_client
         .post(uri!("/api/convert"))
         .header(ContentType::new("application", "json"))
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
         .body(&body)
         .dispatch();
 
     assert_eq!(response.status(), Status::BadRequest);
+    let err = problem(response, "blacklisted_phrase");
     assert_eq!(
-        response.into_string().unwrap(),
+        err["detail"],
         "Blacklisted word! Phrase (things) is not allowed!"
     );
+    assert_eq!(err["field"], "word");
+    assert_eq!(err["value"], "things");
 
     //Check that spaces works
     let body = "{
@@ -92,14 +119,18 @@ fn blacklist_filter() {
     let response = test_client
         .post(uri!("/api/convert"))
         .header(ContentType::new("application", "json"))
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
         .body(&body)
         .dispatch();
 
     assert_eq!(response.status(), Status::BadRequest);
+    let err = problem(response, "blacklisted_phrase");
     assert_eq!(
-        response.into_string().unwrap(),
+        err["detail"],
         "Blacklisted word! Phrase (things) is not allowed!"
     );
+    assert_eq!(err["field"], "word");
+    assert_eq!(err["value"], "things");
 
     //Ensure multiple blocked words returns just the first
     let body = "{
@@ -112,18 +143,226 @@ fn blacklist_filter() {
     let response = test_client
         .post(uri!("/api/convert"))
         .header(ContentType::new("application", "json"))
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
         .body(&body)
         .dispatch();
 
     assert_eq!(response.status(), Status::BadRequest);
+    let err = problem(response, "blacklisted_phrase");
     assert_eq!(
-        response.into_string().unwrap(),
+        err["detail"],
         "Blacklisted word! Phrase (test) is not allowed!"
     );
+    assert_eq!(err["field"], "word");
+    assert_eq!(err["value"], "test");
+}
+
+/// Test that blacklist matches ignore case by default, and may be restricted to whole words
+#[test]
+fn blacklist_modes() {
+    let _u = AlteredToml::with_test_user(PathBuf::from("./config"));
+    let _t = AlteredToml::new(
+        "BLACKLISTED_PHRASES = []",
+        "BLACKLISTED_PHRASES = [\"test\"]",
+        PathType::General,
+        PathBuf::from("./config"),
+    );
+
+    let convert = |client: &Client, word: &str| {
+        client
+            .post(uri!("/api/convert"))
+            .header(ContentType::new("application", "json"))
+            .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+            .body(format!(
+                "{{\"word\": \"{}\", \"lang\": \"en\", \"speed\": 1.0, \"fmt\": \"wav\"}}",
+                word
+            ))
+            .dispatch()
+            .status()
+    };
+
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+    assert_eq!(convert(&client, "TEST"), Status::BadRequest);
+    assert_eq!(convert(&client, "Contest"), Status::BadRequest);
+    drop(client);
+
+    let _m = AlteredToml::new(
+        "BLACKLIST_MODE = \"substring\"",
+        "BLACKLIST_MODE = \"word\"",
+        PathType::General,
+        PathBuf::from("./config"),
+    );
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+    assert_eq!(convert(&client, "a Test, maybe"), Status::BadRequest);
+    assert_eq!(convert(&client, "Contest"), Status::Ok);
+}
+
+/// Test that wordlist files are loaded, both for every language and for a single language
+#[test]
+fn blacklist_wordlists() {
+    let dir = std::env::temp_dir();
+    let global = dir.join(format!("{}.txt", generate_random_alphanumeric(16)));
+    let en = dir.join(format!("{}.txt", generate_random_alphanumeric(16)));
+    let mi = dir.join(format!("{}.txt", generate_random_alphanumeric(16)));
+    std::fs::write(&global, "# Blocked for every language\n\nbogus\n").unwrap();
+    std::fs::write(&en, "bloody\n").unwrap();
+    std::fs::write(&mi, "university\n").unwrap();
+
+    let _u = AlteredToml::with_test_user(PathBuf::from("./config"));
+    let _g = AlteredToml::new(
+        "BLACKLIST_FILES = []",
+        &format!("BLACKLIST_FILES = [{:?}]", global),
+        PathType::General,
+        PathBuf::from("./config"),
+    );
+    let _l = AlteredToml::new(
+        "festvox = \"voices/akl_nz_cw.flitevox\"",
+        &format!(
+            "festvox = \"voices/akl_nz_cw.flitevox\"\nblacklist_files = [{:?}]",
+            en
+        ),
+        PathType::Langs,
+        PathBuf::from("./config"),
+    );
+    let _m = AlteredToml::new(
+        "festvox = \"voices/akl_nz_cwsad.flitevox\"",
+        &format!(
+            "festvox = \"voices/akl_nz_cwsad.flitevox\"\nblacklist_files = [{:?}]",
+            mi
+        ),
+        PathType::Langs,
+        PathBuf::from("./config"),
+    );
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+
+    let convert = |word: &str| {
+        client
+            .post(uri!("/api/convert"))
+            .header(ContentType::new("application", "json"))
+            .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+            .body(format!(
+                "{{\"word\": \"{}\", \"lang\": \"en\", \"speed\": 1.0, \"fmt\": \"wav\"}}",
+                word
+            ))
+            .dispatch()
+    };
+
+    let err = problem(convert("that is BOGUS"), "blacklisted_phrase");
+    assert_eq!(err["value"], "bogus");
+    let err = problem(convert("bloody oath"), "blacklisted_phrase");
+    assert_eq!(err["value"], "bloody");
+
+    //Wordlists of other languages do not apply
+    assert_eq!(convert("The University of Auckland").status(), Status::Ok);
+
+    for f in [global, en, mi] {
+        std::fs::remove_file(f).unwrap();
+    }
+}
+
+/// Test that blacklisted words may be skipped or bleeped instead of rejected
+#[test]
+fn blacklist_policies() {
+    let _u = AlteredToml::with_test_user(PathBuf::from("./config"));
+    let _t = AlteredToml::new(
+        "BLACKLISTED_PHRASES = []",
+        "BLACKLISTED_PHRASES = [\"test\"]",
+        PathType::General,
+        PathBuf::from("./config"),
+    );
+    let _p = AlteredToml::new(
+        "BLACKLIST_POLICY = \"reject\"",
+        "BLACKLIST_POLICY = \"bleep\"",
+        PathType::General,
+        PathBuf::from("./config"),
+    );
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+
+    let convert = |word: &str, policy: Option<&str>| {
+        let policy = policy
+            .map(|p| format!(", \"blacklist_policy\": \"{}\"", p))
+            .unwrap_or_default();
+        client
+            .post(uri!("/api/convert"))
+            .header(ContentType::new("application", "json"))
+            .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+            .body(format!(
+                "{{\"word\": \"{}\", \"lang\": \"en\", \"speed\": 1.0, \"fmt\": \"wav\"{}}}",
+                word, policy
+            ))
+            .dispatch()
+    };
+
+    //Skipped words are removed from the phrase, so it is read as if they were never sent
+    let skipped = convert("hello test world", Some("skip"));
+    assert_eq!(skipped.status(), Status::Ok);
+    let plain = convert("hello world", None);
+    assert_eq!(plain.status(), Status::Ok);
+    assert_eq!(
+        skipped.headers().get_one("ETag"),
+        plain.headers().get_one("ETag")
+    );
+    let err = problem(convert("test", Some("skip")), "empty_phrase");
+    assert_eq!(err["status"], 400);
+
+    //Bleeped words are replaced by a tone, as configured for this deployment
+    let hello = convert("hello", None).into_bytes().unwrap();
+    let bleeped = convert("hello test", None);
+    assert_eq!(bleeped.status(), Status::Ok);
+    assert_eq!(
+        bleeped.headers().get_one("content-type").unwrap(),
+        "audio/wav"
+    );
+    let bleeped = bleeped.into_bytes().unwrap();
+    assert_eq!(&bleeped[0..4], b"RIFF");
+    assert!(bleeped.len() > hello.len());
+
+    //Clients may still choose to have the request rejected, including through the url based endpoint
+    problem(convert("hello test", Some("reject")), "blacklisted_phrase");
+    let response = client
+        .get("/api/convert?word=test&lang=en&speed=1&fmt=wav&blacklist_policy=reject")
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+        .dispatch();
+    problem(response, "blacklisted_phrase");
+}
+
+/// Test that a blacklisted pattern which only matches an empty part of the phrase is rejected under every policy,
+/// as there is nothing to skip or bleep
+#[test]
+fn blacklist_unlocatable() {
+    let _u = AlteredToml::with_test_user(PathBuf::from("./config"));
+    let _t = AlteredToml::new(
+        "BLACKLISTED_PHRASES = []",
+        "BLACKLISTED_PHRASES = [\"^\"]",
+        PathType::General,
+        PathBuf::from("./config"),
+    );
+    let _m = AlteredToml::new(
+        "BLACKLIST_MODE = \"substring\"",
+        "BLACKLIST_MODE = \"regex\"",
+        PathType::General,
+        PathBuf::from("./config"),
+    );
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+
+    for policy in ["reject", "skip", "bleep"] {
+        let response = client
+            .post(uri!("/api/convert"))
+            .header(ContentType::new("application", "json"))
+            .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+            .body(format!(
+                "{{\"word\": \"hello\", \"lang\": \"en\", \"speed\": 1.0, \"fmt\": \"wav\", \"blacklist_policy\": \"{}\"}}",
+                policy
+            ))
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest, "policy {}", policy);
+        problem(response, "blacklisted_phrase");
+    }
 }
 
 #[test]
 fn success_conversion() {
+    let _u = AlteredToml::with_test_user(PathBuf::from("./config"));
     let client = Client::tracked(rocket()).expect("valid rocket instance");
 
     let body = "{
@@ -137,6 +376,7 @@ fn success_conversion() {
     let response = client
         .post(uri!("/api/convert"))
         .header(ContentType::new("application", "json"))
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
         .body(&body)
         .dispatch();
 
@@ -151,7 +391,7 @@ fn success_conversion() {
 
     assert_eq!(
         response.headers().get_one("content-type").unwrap(),
-        "audio/mpeg"
+        "audio/wav"
     );
 
     assert_eq!(
@@ -159,10 +399,309 @@ fn success_conversion() {
         "attachment; filename=\"output.wav\""
     );
     assert!(response.into_bytes().unwrap().len() > 30000);
+
+    //Clients may request the file be played inline
+    let response = client
+        .post("/api/convert?disposition=inline")
+        .header(ContentType::new("application", "json"))
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+        .body(body)
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.headers().get_one("content-disposition").unwrap(),
+        "inline; filename=\"output.wav\""
+    );
+}
+
+/// Validate that clients may revalidate their copy of a phrase, and request parts of it
+#[test]
+fn conditional_conversion() {
+    let _u = AlteredToml::with_test_user(PathBuf::from("./config"));
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+
+    let body = "{
+        \"word\": \"The University of Auckland\",
+        \"lang\": \"en\",
+        \"speed\": 1.0,
+        \"fmt\": \"wav\"
+    }";
+    let convert = |headers: &[(&'static str, &str)]| {
+        let mut request = client
+            .post(uri!("/api/convert"))
+            .header(ContentType::new("application", "json"))
+            .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+            .body(body);
+        for (name, value) in headers {
+            request = request.header(Header::new(*name, value.to_string()));
+        }
+        request.dispatch()
+    };
+
+    let response = convert(&[]);
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.headers().get_one("cache-control").unwrap(),
+        "private, max-age=86400"
+    );
+    assert_eq!(
+        response.headers().get_one("accept-ranges").unwrap(),
+        "bytes"
+    );
+    let etag = response.headers().get_one("etag").unwrap().to_owned();
+    let file = response.into_bytes().unwrap();
+
+    //The same phrase produces the same etag, which may be used to revalidate
+    let response = convert(&[("If-None-Match", &etag)]);
+    assert_eq!(response.status(), Status::NotModified);
+    assert_eq!(response.headers().get_one("etag").unwrap(), etag);
+    assert!(response.into_bytes().unwrap_or_default().is_empty());
+
+    let response = convert(&[("If-None-Match", "\"some-other-file.wav\"")]);
+    assert_eq!(response.status(), Status::Ok);
+
+    //Ranges of the file may be requested
+    let response = convert(&[("Range", "bytes=100-199")]);
+    assert_eq!(response.status(), Status::PartialContent);
+    assert_eq!(
+        response.headers().get_one("content-range").unwrap(),
+        format!("bytes 100-199/{}", file.len())
+    );
+    assert_eq!(response.into_bytes().unwrap(), &file[100..200]);
+
+    let response = convert(&[("Range", "bytes=-50"), ("If-Range", &etag)]);
+    assert_eq!(response.status(), Status::PartialContent);
+    assert_eq!(response.into_bytes().unwrap(), &file[file.len() - 50..]);
+
+    //A stale If-Range falls back to the full file
+    let response = convert(&[("Range", "bytes=0-9"), ("If-Range", "\"stale.wav\"")]);
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_bytes().unwrap(), file);
+
+    let response = convert(&[("Range", &format!("bytes={}-", file.len()))]);
+    assert_eq!(response.status(), Status::RangeNotSatisfiable);
+    assert_eq!(
+        response.headers().get_one("content-range").unwrap(),
+        format!("bytes */{}", file.len())
+    );
+    problem(response, "range_not_satisfiable");
+}
+
+/// Validate that phrases may be converted through a url, authenticated by a query parameter
+#[test]
+fn get_conversion() {
+    let _u = AlteredToml::with_test_user(PathBuf::from("./config"));
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+
+    let url = format!(
+        "/api/convert?word=The%20University%20of%20Auckland&lang=en&speed=1.0&fmt=wav&api_key={}",
+        TEST_API_KEY
+    );
+    let response = client.get(&url).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.headers().get_one("content-type").unwrap(),
+        "audio/wav"
+    );
+    let file = response.into_bytes().unwrap();
+    assert!(file.len() > 30000);
+
+    //The same phrase is produced as when posted
+    let response = client
+        .post(uri!("/api/convert"))
+        .header(ContentType::new("application", "json"))
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+        .body(
+            "{
+            \"word\": \"The University of Auckland\",
+            \"lang\": \"en\",
+            \"speed\": 1.0,
+            \"fmt\": \"wav\"
+        }",
+        )
+        .dispatch();
+    assert_eq!(response.into_bytes().unwrap(), file);
+
+    //Head requests return the headers of the file without the body
+    let response = client.head(&url).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.headers().get_one("content-type").unwrap(),
+        "audio/wav"
+    );
+    assert!(response.into_bytes().unwrap_or_default().is_empty());
+
+    //Phrases are validated in the same way
+    let response = client
+        .get("/api/convert?word=test&lang=en&speed=1.0&fmt=this-will-never-exist")
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    problem(response, "unsupported_format");
+
+    let response = client
+        .get("/api/convert?word=test&lang=en")
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    assert_eq!(problem(response, "invalid_query")["field"], "speed");
+
+    let response = client
+        .get("/api/convert?word=test&lang=en&speed=1.0&fmt=wav&api_key=not-a-real-key")
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+    problem(response, "unauthorized");
+}
+
+/// Validate that a format is chosen from the accept header when none is provided
+#[test]
+fn negotiated_conversion() {
+    let _u = AlteredToml::with_test_user(PathBuf::from("./config"));
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+
+    let body = "{
+        \"word\": \"The University of Auckland\",
+        \"lang\": \"en\",
+        \"speed\": 1.0
+    }";
+    let convert = |accept: Option<&'static str>| {
+        let mut request = client
+            .post(uri!("/api/convert"))
+            .header(ContentType::new("application", "json"))
+            .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+            .body(body);
+        if let Some(accept) = accept {
+            request = request.header(Header::new("Accept", accept));
+        }
+        request.dispatch()
+    };
+
+    for (accept, expected) in [
+        (None, "audio/wav"),
+        (Some("*/*"), "audio/wav"),
+        (Some("audio/*"), "audio/wav"),
+        (Some("audio/mpeg"), "audio/mpeg"),
+        (
+            Some("audio/ogg;q=0.9, audio/mpeg;q=0.5, audio/wav;q=0.4"),
+            "audio/mpeg",
+        ),
+        (Some("audio/wav;q=0.2, audio/aiff"), "audio/aiff"),
+        (Some("audio/*, audio/wav;q=0"), "audio/aiff"),
+        (Some("audio/*, audio/mpeg"), "audio/mpeg"),
+        (Some("*/*, audio/*;q=0.9, audio/mpeg"), "audio/mpeg"),
+    ] {
+        let response = convert(accept);
+        assert_eq!(response.status(), Status::Ok, "accepting {:?}", accept);
+        assert_eq!(
+            response.headers().get_one("content-type").unwrap(),
+            expected,
+            "accepting {:?}",
+            accept
+        );
+    }
+
+    //Formats which are not allowed may not be negotiated
+    let response = convert(Some("audio/flac, text/html"));
+    assert_eq!(response.status(), Status::NotAcceptable);
+    problem(response, "not_acceptable");
+
+    //An empty format is not negotiated, as it was provided
+    let response = client
+        .post(uri!("/api/convert"))
+        .header(ContentType::new("application", "json"))
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+        .header(Header::new("Accept", "audio/mpeg"))
+        .body("{\"word\": \"hello\", \"lang\": \"en\", \"speed\": 1.0, \"fmt\": \"\"}")
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    problem(response, "unsupported_format");
+
+    //The same applies to the url based endpoint
+    let response = client
+        .get("/api/convert?word=hello&lang=en&speed=1.0")
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+        .header(Header::new("Accept", "audio/mpeg"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.headers().get_one("content-type").unwrap(),
+        "audio/mpeg"
+    );
+}
+
+/// Validate that converted phrases may be retrieved again from the url they are served with
+#[test]
+fn cached_audio() {
+    let _u = AlteredToml::with_test_user(PathBuf::from("./config"));
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+
+    let response = client
+        .post(uri!("/api/convert"))
+        .header(ContentType::new("application", "json"))
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+        .body(
+            "{
+            \"word\": \"The University of Auckland\",
+            \"lang\": \"en\",
+            \"speed\": 1.0,
+            \"fmt\": \"mp3\"
+        }",
+        )
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let location = response
+        .headers()
+        .get_one("content-location")
+        .unwrap()
+        .to_owned();
+    assert!(location.starts_with("/api/audio/") && location.ends_with(".mp3"));
+    let file = response.into_bytes().unwrap();
+
+    let response = client
+        .get(&location)
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.headers().get_one("content-type").unwrap(),
+        "audio/mpeg"
+    );
+    assert_eq!(
+        response.headers().get_one("content-location").unwrap(),
+        location
+    );
+    assert_eq!(response.into_bytes().unwrap(), file);
+
+    //Authentication is still required
+    let response = client.get(&location).dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    //Phrases which have not been generated are not found, rather than generated
+    let response = client
+        .get(format!("/api/audio/{}.mp3", "0".repeat(64)))
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+    problem(response, "audio_not_found");
+
+    let response = client
+        .get("/api/audio/not-a-hash.mp3")
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    problem(response, "invalid_hash");
+
+    let response = client
+        .get(format!("/api/audio/{}.exe", "0".repeat(64)))
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    problem(response, "unsupported_format");
 }
 
 #[test]
 fn invalid_conversion_strings() {
+    let _u = AlteredToml::with_test_user(PathBuf::from("./config"));
     //List of potentially "invalid" phrases to test
     //When the sytem tries to create the file on the disk
     //Note that we are *not* testing that the api rejects these strings, we are only testing
@@ -199,6 +738,7 @@ fn invalid_conversion_strings() {
         let response = client
             .post(uri!("/api/convert"))
             .header(ContentType::new("application", "json"))
+            .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
             .body(&body)
             .dispatch();
 
@@ -209,6 +749,7 @@ fn invalid_conversion_strings() {
 /// Validate that all file format options work as intended
 #[test]
 fn test_every_format() {
+    let _u = AlteredToml::with_test_user(PathBuf::from("./config"));
     let cfg: Config = Config::new(PathBuf::from("./config")).unwrap();
     for format in cfg.ALLOWED_FORMATS().iter() {
         let client = Client::tracked(rocket()).expect("valid rocket instance");
@@ -227,6 +768,7 @@ fn test_every_format() {
         let response = client
             .post(uri!("/api/convert"))
             .header(ContentType::new("application", "json"))
+            .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
             .body(&body)
             .dispatch();
 
@@ -243,6 +785,13 @@ fn test_every_format() {
             response.headers().get_one("content-disposition").unwrap(),
             expected
         );
+        let content_type = response.headers().get_one("content-type").unwrap();
+        assert!(
+            content_type.starts_with("audio/"),
+            "unexpected content type {} for {}",
+            content_type,
+            format
+        );
     }
 }
 
@@ -255,6 +804,7 @@ fn test_every_lang() {
 /// A simple test which ensures that an invalid file format fails out as expected.
 #[test]
 fn test_invalid_formats() {
+    let _u = AlteredToml::with_test_user(PathBuf::from("./config"));
     let client = Client::tracked(rocket()).expect("valid rocket instance");
 
     let body = "{
@@ -268,6 +818,7 @@ fn test_invalid_formats() {
     let response = client
         .post(uri!("/api/convert"))
         .header(ContentType::new("application", "json"))
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
         .body(&body)
         .dispatch();
 
@@ -280,15 +831,20 @@ fn test_invalid_formats() {
         );
     }
 
-    let body = response.into_string().expect("a valid body");
+    let err = problem(response, "unsupported_format");
     assert_eq!(
-        body,
-        String::from("Requested format (this-will-never-exist) is not supported by this api!")
+        err["detail"],
+        "Requested format (this-will-never-exist) is not supported by this api!"
     );
+    assert_eq!(err["status"], 400);
+    assert_eq!(err["title"], "Bad Request");
+    assert_eq!(err["field"], "fmt");
+    assert_eq!(err["value"], "this-will-never-exist");
 }
 
 #[test]
 fn test_speed() {
+    let _u = AlteredToml::with_test_user(PathBuf::from("./config"));
     let cfg: Config = Config::new(PathBuf::from("./config")).unwrap();
 
     for format in cfg.ALLOWED_FORMATS().iter() {
@@ -316,12 +872,14 @@ fn test_speed() {
         let response_normal = client
             .post(uri!("/api/convert"))
             .header(ContentType::new("application", "json"))
+            .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
             .body(&normal)
             .dispatch();
 
         let response_fast = client
             .post(uri!("/api/convert"))
             .header(ContentType::new("application", "json"))
+            .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
             .body(&fast)
             .dispatch();
 
@@ -338,3 +896,353 @@ fn test_speed() {
         assert!(diff < 0.05);
     }
 }
+
+/// Validate that a batch request returns an archive with a file per valid phrase, and a manifest
+/// describing any phrases which failed
+#[test]
+fn batch_conversion() {
+    let _u = AlteredToml::with_test_user(PathBuf::from("./config"));
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+
+    let body = "[
+        {
+            \"word\": \"The University of Auckland\",
+            \"lang\": \"en\",
+            \"speed\": 1.0,
+            \"fmt\": \"wav\"
+        },
+        {
+            \"word\": \"hello\",
+            \"lang\": \"en\",
+            \"speed\": 1.0,
+            \"fmt\": \"this-will-never-exist\"
+        },
+        {
+            \"word\": \"world\",
+            \"lang\": \"en\",
+            \"speed\": 2.0,
+            \"fmt\": \"mp3\"
+        }
+    ]";
+
+    let response = client
+        .post(uri!("/api/convert/batch"))
+        .header(ContentType::new("application", "json"))
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+        .body(body)
+        .dispatch();
+
+    let status = response.status();
+    if status != Status::Ok {
+        panic!(
+            "Failed with status {} \nBody: \n{}\n",
+            status,
+            response.into_string().unwrap()
+        );
+    }
+
+    assert_eq!(
+        response.headers().get_one("content-type").unwrap(),
+        "application/zip"
+    );
+
+    let data = response.into_bytes().unwrap();
+    let mut archive = zip::ZipArchive::new(Cursor::new(data)).expect("a valid zip archive");
+    assert_eq!(archive.len(), 3);
+    assert!(archive.by_name("0.wav").unwrap().size() > 30000);
+    assert!(archive.by_name("2.mp3").is_ok());
+
+    let mut manifest = String::new();
+    archive
+        .by_name("manifest.json")
+        .expect("a manifest")
+        .read_to_string(&mut manifest)
+        .unwrap();
+    let manifest: serde_json::Value = serde_json::from_str(&manifest).expect("a valid manifest");
+
+    assert_eq!(manifest[0]["index"], 0);
+    assert_eq!(manifest[0]["file"], "0.wav");
+    assert_eq!(manifest[1]["index"], 1);
+    assert_eq!(manifest[1]["code"], "unsupported_format");
+    assert_eq!(
+        manifest[1]["error"],
+        "Requested format (this-will-never-exist) is not supported by this api!"
+    );
+    assert_eq!(manifest[2]["file"], "2.mp3");
+}
+
+/// Validate that phrases in a batch without a format are given one from the `Accept` header of the request
+#[test]
+fn batch_negotiated() {
+    let _u = AlteredToml::with_test_user(PathBuf::from("./config"));
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+
+    let body = "[
+        {
+            \"word\": \"hello\",
+            \"lang\": \"en\",
+            \"speed\": 1.0
+        },
+        {
+            \"word\": \"world\",
+            \"lang\": \"en\",
+            \"speed\": 1.0,
+            \"fmt\": \"wav\"
+        }
+    ]";
+    let batch = |accept: Option<&'static str>| {
+        let mut request = client
+            .post(uri!("/api/convert/batch"))
+            .header(ContentType::new("application", "json"))
+            .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+            .body(body);
+        if let Some(accept) = accept {
+            request = request.header(Header::new("Accept", accept));
+        }
+        let response = request.dispatch();
+        assert_eq!(response.status(), Status::Ok, "accepting {:?}", accept);
+        let data = response.into_bytes().unwrap();
+        let mut archive = zip::ZipArchive::new(Cursor::new(data)).expect("a valid zip archive");
+        let mut manifest = String::new();
+        archive
+            .by_name("manifest.json")
+            .expect("a manifest")
+            .read_to_string(&mut manifest)
+            .unwrap();
+        let manifest: serde_json::Value =
+            serde_json::from_str(&manifest).expect("a valid manifest");
+        manifest
+    };
+
+    let manifest = batch(None);
+    assert_eq!(manifest[0]["file"], "0.wav");
+    assert_eq!(manifest[1]["file"], "1.wav");
+
+    let manifest = batch(Some("audio/mpeg"));
+    assert_eq!(manifest[0]["file"], "0.mp3");
+    assert_eq!(manifest[1]["file"], "1.wav");
+
+    let manifest = batch(Some("text/html"));
+    assert_eq!(manifest[0]["code"], "not_acceptable");
+    assert_eq!(manifest[1]["file"], "1.wav");
+}
+
+/// Validate that batches which are empty or larger than the configured limit are rejected
+#[test]
+fn batch_limits() {
+    let _u = AlteredToml::with_test_user(PathBuf::from("./config"));
+    let cfg: Config = Config::new(PathBuf::from("./config")).unwrap();
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+
+    let response = client
+        .post(uri!("/api/convert/batch"))
+        .header(ContentType::new("application", "json"))
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+        .body("[]")
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    problem(response, "empty_batch");
+
+    let phrase = "{
+        \"word\": \"hello\",
+        \"lang\": \"en\",
+        \"speed\": 1.0,
+        \"fmt\": \"wav\"
+    }";
+    let body = format!("[{}]", vec![phrase; cfg.BATCH_SIZE_LIMIT() + 1].join(","));
+
+    let response = client
+        .post(uri!("/api/convert/batch"))
+        .header(ContentType::new("application", "json"))
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+        .body(body)
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    assert_eq!(
+        problem(response, "batch_too_large")["detail"],
+        format!(
+            "Batch is too large! Greater than {} phrases",
+            cfg.BATCH_SIZE_LIMIT()
+        )
+    );
+}
+
+/// Validate that conversion requires a valid api key
+#[test]
+fn unauthorized_conversion() {
+    let _u = AlteredToml::with_test_user(PathBuf::from("./config"));
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+
+    let body = "{
+        \"word\": \"The University of Auckland\",
+        \"lang\": \"en\",
+        \"speed\": 1.0,
+        \"fmt\": \"wav\"
+    }";
+
+    //No key provided
+    let response = client
+        .post(uri!("/api/convert"))
+        .header(ContentType::new("application", "json"))
+        .body(body)
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+    assert_eq!(
+        problem(response, "unauthorized")["detail"],
+        format!(
+            "A valid api key must be provided in the {} header, or the {} query parameter!",
+            API_KEY_HEADER, API_KEY_QUERY
+        )
+    );
+
+    //Invalid key provided
+    let response = client
+        .post(uri!("/api/convert"))
+        .header(ContentType::new("application", "json"))
+        .header(Header::new(API_KEY_HEADER, "not-a-real-key"))
+        .body(body)
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+    problem(response, "unauthorized");
+
+    //Disabled user
+    let _d = AlteredToml::new(
+        "enabled = true",
+        "enabled = false",
+        PathType::Users,
+        PathBuf::from("./config"),
+    );
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+    let response = client
+        .post(uri!("/api/convert"))
+        .header(ContentType::new("application", "json"))
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+        .body(body)
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+    problem(response, "forbidden");
+
+    //Malformed bodies are also reported as problems
+    drop(_d);
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+    let response = client
+        .post(uri!("/api/convert"))
+        .header(ContentType::new("application", "json"))
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+        .body("{\"word\": 3}")
+        .dispatch();
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+    problem(response, "unprocessable_entity");
+}
+
+/// Validate that clients exceeding their generation budget are rate limited, while cached phrases
+/// are charged against a separate budget
+#[test]
+fn rate_limited_conversion() {
+    let _u = AlteredToml::with_test_user(PathBuf::from("./config"));
+    let _t = AlteredToml::new(
+        "RATE_LIMIT_GENERATED_PER_MIN = 20",
+        "RATE_LIMIT_GENERATED_PER_MIN = 1",
+        PathType::General,
+        PathBuf::from("./config"),
+    );
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+
+    let phrase = |word: &str| {
+        format!(
+            "{{
+            \"word\": \"{}\",
+            \"lang\": \"en\",
+            \"speed\": 1.0,
+            \"fmt\": \"wav\"
+        }}",
+            word
+        )
+    };
+    let first = phrase(&generate_random_alphanumeric(16));
+    let second = phrase(&generate_random_alphanumeric(16));
+
+    let response = client
+        .post(uri!("/api/convert"))
+        .header(ContentType::new("application", "json"))
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+        .body(&first)
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    //A second generation exceeds the budget
+    let response = client
+        .post(uri!("/api/convert"))
+        .header(ContentType::new("application", "json"))
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+        .body(&second)
+        .dispatch();
+    assert_eq!(response.status(), Status::TooManyRequests);
+    let retry_after: u64 = response
+        .headers()
+        .get_one("Retry-After")
+        .expect("a retry-after header")
+        .parse()
+        .expect("a number of seconds");
+    assert!(retry_after > 0 && retry_after <= 60);
+    problem(response, "rate_limited");
+
+    //The first phrase is now cached, so is still available
+    let response = client
+        .post(uri!("/api/convert"))
+        .header(ContentType::new("application", "json"))
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+        .body(&first)
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+}
+
+/// Validate that a batch holding more uncached phrases than the generation budget converts those that fit,
+/// and reports the rest in the manifest, rather than rejecting the whole batch
+#[test]
+fn rate_limited_batch() {
+    let _u = AlteredToml::with_test_user(PathBuf::from("./config"));
+    let cfg: Config = Config::new(PathBuf::from("./config")).unwrap();
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+
+    let budget = cfg.RATE_LIMIT_GENERATED_PER_MIN() as usize;
+    let phrases: Vec<String> = (0..=budget)
+        .map(|_| {
+            format!(
+                "{{
+                \"word\": \"{}\",
+                \"lang\": \"en\",
+                \"speed\": 1.0,
+                \"fmt\": \"wav\"
+            }}",
+                generate_random_alphanumeric(16)
+            )
+        })
+        .collect();
+    let body = format!("[{}]", phrases.join(","));
+
+    let response = client
+        .post(uri!("/api/convert/batch"))
+        .header(ContentType::new("application", "json"))
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+        .body(body)
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let data = response.into_bytes().unwrap();
+    let mut archive = zip::ZipArchive::new(Cursor::new(data)).expect("a valid zip archive");
+    assert_eq!(archive.len(), budget + 1);
+
+    let mut manifest = String::new();
+    archive
+        .by_name("manifest.json")
+        .expect("a manifest")
+        .read_to_string(&mut manifest)
+        .unwrap();
+    let manifest: serde_json::Value = serde_json::from_str(&manifest).expect("a valid manifest");
+    for index in 0..budget {
+        assert_eq!(manifest[index]["file"], format!("{}.wav", index));
+    }
+    assert_eq!(manifest[budget]["index"], budget);
+    assert_eq!(manifest[budget]["code"], "rate_limited");
+}
@@ -0,0 +1,88 @@
+use config::PathType;
+use festival_api::rocket;
+use rocket::http::{ContentType, Status};
+use rocket::local::blocking::Client;
+use rocket::uri;
+use std::path::PathBuf;
+use utils::test_utils::AlteredToml;
+
+/// Fetch the rendered specification from a client
+fn spec(client: &Client) -> serde_json::Value {
+    let response = client.get(uri!("/openapi.json")).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::JSON));
+    serde_json::from_str(&response.into_string().unwrap()).expect("a valid specification")
+}
+
+/// Find the schema of a query parameter of the url based convert endpoint
+fn query_schema<'a>(spec: &'a serde_json::Value, name: &str) -> &'a serde_json::Value {
+    spec["paths"]["/api/convert"]["get"]["parameters"]
+        .as_array()
+        .expect("convert parameters")
+        .iter()
+        .find(|p| p["name"] == name)
+        .map(|p| &p["schema"])
+        .expect("a documented parameter")
+}
+
+/// Test that the specification is filled in from the configuration
+#[test]
+fn openapi_spec() {
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+    let spec = spec(&client);
+
+    assert_eq!(spec["info"]["version"], env!("CARGO_PKG_VERSION"));
+    let package = &spec["components"]["schemas"]["PhrasePackage"]["properties"];
+    assert_eq!(package["lang"]["enum"], serde_json::json!(["en"]));
+    assert_eq!(
+        package["fmt"]["enum"],
+        serde_json::json!(["aif", "mp3", "wav"])
+    );
+    assert_eq!(package["speed"]["minimum"], 0.5);
+    assert_eq!(package["speed"]["maximum"], 3.0);
+
+    assert_eq!(query_schema(&spec, "lang")["enum"], package["lang"]["enum"]);
+    assert_eq!(query_schema(&spec, "fmt")["enum"], package["fmt"]["enum"]);
+    assert_eq!(query_schema(&spec, "speed")["maximum"], 3.0);
+}
+
+/// Test that changes to the configuration are reflected in the specification
+#[test]
+fn openapi_spec_follows_config() {
+    let _s = AlteredToml::new(
+        "SPEED_MAX_VAL = 3.0",
+        "SPEED_MAX_VAL = 2.0",
+        PathType::General,
+        PathBuf::from("./config"),
+    );
+    let _f = AlteredToml::new(
+        "ALLOWED_FORMATS = [ \"wav\", \"mp3\", \"aif\" ]",
+        "ALLOWED_FORMATS = [ \"wav\", \"mp3\" ]",
+        PathType::General,
+        PathBuf::from("./config"),
+    );
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+    let spec = spec(&client);
+
+    let package = &spec["components"]["schemas"]["PhrasePackage"]["properties"];
+    assert_eq!(package["fmt"]["enum"], serde_json::json!(["mp3", "wav"]));
+    assert_eq!(package["speed"]["maximum"], 2.0);
+    assert_eq!(query_schema(&spec, "speed")["maximum"], 2.0);
+}
+
+/// Test that the docs page is served, and points at the specification
+#[test]
+fn docs_page() {
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+    let response = client.get(uri!("/docs")).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.headers().get_one("Content-Type").unwrap(),
+        "text/html; charset=utf-8"
+    );
+    let page = response.into_string().unwrap();
+    assert!(page.contains("/openapi.json"));
+
+    //The name of the api is escaped, as it may contain any chars
+    assert!(page.contains("<title>SE206&#x27;s TTS API</title>"));
+}
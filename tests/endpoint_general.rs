@@ -1,7 +1,10 @@
+use config::{Config, ConfigIssue, PathType, Severity};
 use festival_api::rocket;
 use rocket::http::Status;
 use rocket::local::blocking::Client;
 use rocket::uri;
+use std::path::PathBuf;
+use utils::test_utils::AlteredToml;
 
 //***** Test Methods *****//
 
@@ -15,7 +18,119 @@ fn test_index() {
             .headers()
             .get_one("Content-Type")
             .expect("a content type header"),
-        "text/plain; charset=utf-8"
+        "text/html; charset=utf-8"
     );
-    assert!(!response.into_string().unwrap().is_empty());
+
+    //The playground only offers enabled languages, and formats which may be produced
+    let page = response.into_string().unwrap();
+    assert!(page.contains("<option value=\"en\">english</option>"));
+    assert!(!page.contains("<option value=\"mi\">"));
+    assert!(page.contains("<option value=\"wav\" selected>wav</option>"));
+    assert!(page.contains("<option value=\"mp3\">mp3</option>"));
+    assert!(page.contains("min=\"0.5\" max=\"3\" step=\"0.5\""));
+    assert!(!page.contains("{{"));
+}
+
+/// Validate that every configured language is reported, including disabled ones
+#[test]
+fn test_langs() {
+    let cfg: Config = Config::new(PathBuf::from("./config")).unwrap();
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+
+    let response = client.get(uri!("/api/langs")).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response
+            .headers()
+            .get_one("Content-Type")
+            .expect("a content type header"),
+        "application/json; charset=utf-8"
+    );
+
+    let body: serde_json::Value =
+        serde_json::from_str(&response.into_string().unwrap()).expect("a valid json response");
+    let langs = body.as_array().expect("an array of languages");
+    assert_eq!(langs.len(), cfg.SUPPORTED_LANGS().len());
+
+    for lang in langs {
+        let code = lang["iso_691_code"].as_str().expect("an iso code");
+        let expected = cfg.SUPPORTED_LANGS().get(code).expect("a configured lang");
+        assert_eq!(lang["display_name"], expected.display_name.as_str());
+        assert_eq!(lang["festival_code"], expected.festival_code.as_str());
+        assert_eq!(lang["enabled"], expected.enabled);
+    }
+}
+
+/// Validate that only allowed formats are reported, each with a converter and audio MIME type
+#[test]
+fn test_formats() {
+    let cfg: Config = Config::new(PathBuf::from("./config")).unwrap();
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+
+    let response = client.get(uri!("/api/formats")).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let body: serde_json::Value =
+        serde_json::from_str(&response.into_string().unwrap()).expect("a valid json response");
+    let formats = body.as_array().expect("an array of formats");
+    assert!(!formats.is_empty());
+
+    for format in formats {
+        let fmt = format["format"].as_str().expect("a format");
+        assert!(cfg.ALLOWED_FORMATS().contains(fmt));
+        assert_eq!(format["converter"], "ffmpeg");
+        assert!(format["mime_type"]
+            .as_str()
+            .expect("a mime type")
+            .starts_with("audio/"));
+    }
+}
+
+/// Validate that blacklisted phrases which could never match are ignored, with a warning
+#[test]
+fn test_config_warnings() {
+    let _t = AlteredToml::new(
+        "BLACKLISTED_PHRASES = []",
+        "BLACKLISTED_PHRASES = [\"test\", \"t*st\"]",
+        PathType::General,
+        PathBuf::from("./config"),
+    );
+    let cfg: Config = Config::new(PathBuf::from("./config")).unwrap();
+    assert_eq!(cfg.BLACKLISTED_PHRASES(), ["test"]);
+
+    let issue = cfg
+        .ISSUES()
+        .iter()
+        .find(|i| matches!(i, ConfigIssue::DisallowedBlacklistChar { .. }))
+        .expect("a warning for the ignored phrase");
+    assert_eq!(
+        issue,
+        &ConfigIssue::DisallowedBlacklistChar {
+            phrase: String::from("t*st"),
+            c: '*'
+        }
+    );
+    assert_eq!(issue.severity(), Severity::Warning);
+
+    //Warnings do not prevent the api from launching
+    Client::tracked(rocket()).expect("valid rocket instance");
+}
+
+/// Validate that the api refuses to launch when two languages share an iso code
+#[test]
+fn test_config_errors() {
+    let _t = AlteredToml::new(
+        "iso_691-1_code = \"mi\"",
+        "iso_691-1_code = \"en\"",
+        PathType::Langs,
+        PathBuf::from("./config"),
+    );
+    let cfg: Config = Config::new(PathBuf::from("./config")).unwrap();
+    assert!(cfg.ISSUES().contains(&ConfigIssue::DuplicateIsoCode {
+        code: String::from("en"),
+        langs: vec![String::from("english"), String::from("maori")],
+    }));
+
+    let launch = std::panic::catch_unwind(|| Client::tracked(rocket()).is_ok());
+    assert!(launch.is_err());
 }
@@ -0,0 +1,82 @@
+use config::PathType;
+use festival_api::rocket;
+use rocket::http::{ContentType, Status};
+use rocket::local::blocking::Client;
+use rocket::uri;
+use std::path::PathBuf;
+use utils::test_utils::AlteredToml;
+
+/// Test that the liveness probe always succeeds
+#[test]
+fn health_live() {
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+    let response = client.get(uri!("/health/live")).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::JSON));
+}
+
+/// Test that the readiness probe fails when an enabled language is missing its voice file
+#[test]
+fn health_ready_missing_voice() {
+    let _l = AlteredToml::new(
+        "festvox = \"voices/akl_nz_cw.flitevox\"",
+        "festvox = \"voices/this-will-never-exist.flitevox\"",
+        PathType::Langs,
+        PathBuf::from("./config"),
+    );
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+    let response = client.get(uri!("/health/ready")).dispatch();
+    assert_eq!(response.status(), Status::ServiceUnavailable);
+
+    let body: serde_json::Value =
+        serde_json::from_str(&response.into_string().unwrap()).expect("a valid json response");
+    assert_eq!(body["ready"], false);
+    assert_eq!(body["checks"]["voices"]["ok"], false);
+    assert!(body["checks"]["voices"]["error"]
+        .as_str()
+        .unwrap()
+        .contains("voices/this-will-never-exist.flitevox (en)"));
+    assert_eq!(body["checks"]["cache"]["ok"], true);
+}
+
+/// Test that the readiness probe succeeds when every check passes, disabled languages are not checked
+#[test]
+fn health_ready() {
+    let _l = AlteredToml::new(
+        "festvox = \"voices/akl_nz_cw.flitevox\"",
+        "festvox = \"Cargo.toml\"",
+        PathType::Langs,
+        PathBuf::from("./config"),
+    );
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+    let response = client.get(uri!("/health/ready")).dispatch();
+    let status = response.status();
+
+    let body: serde_json::Value =
+        serde_json::from_str(&response.into_string().unwrap()).expect("a valid json response");
+    assert_eq!(status, Status::Ok, "readiness failed: {}", body);
+    assert_eq!(body["ready"], true);
+    for check in ["flite", "ffmpeg", "voices", "cache"] {
+        assert_eq!(body["checks"][check]["ok"], true);
+        assert!(body["checks"][check].get("error").is_none());
+    }
+}
+
+/// Test that a voice built in to flite is not reported as a missing voice file, matching the validation of the config
+#[test]
+fn health_ready_builtin_voice() {
+    let _l = AlteredToml::new(
+        "festvox = \"voices/akl_nz_cw.flitevox\"",
+        "festvox = \"kal\"",
+        PathType::Langs,
+        PathBuf::from("./config"),
+    );
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+    let response = client.get(uri!("/health/ready")).dispatch();
+    let status = response.status();
+
+    let body: serde_json::Value =
+        serde_json::from_str(&response.into_string().unwrap()).expect("a valid json response");
+    assert_eq!(status, Status::Ok, "readiness failed: {}", body);
+    assert_eq!(body["checks"]["voices"]["ok"], true);
+}
@@ -0,0 +1,240 @@
+use config::{PathType, API_KEY_HEADER};
+use festival_api::rocket;
+use rocket::http::{ContentType, Header, Status};
+use rocket::local::blocking::Client;
+use rocket::uri;
+use std::path::PathBuf;
+use std::time::Duration;
+use utils::generate_random_alphanumeric;
+use utils::phrase_package::PhrasePackage;
+use utils::test_utils::{AlteredToml, TEST_API_KEY};
+
+/// Poll a job until it is no longer queued or running, panicking if it takes too long
+fn wait_for_job(client: &Client, id: &str) -> serde_json::Value {
+    for _ in 0..100 {
+        let response = client
+            .get(format!("/api/jobs/{}", id))
+            .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        let body: serde_json::Value =
+            serde_json::from_str(&response.into_string().unwrap()).expect("a valid json response");
+        match body["status"].as_str().expect("a status") {
+            "queued" | "running" => std::thread::sleep(Duration::from_millis(100)),
+            _ => return body,
+        }
+    }
+    panic!("job {} did not finish in time", id);
+}
+
+/// Test that a job can be created, polled and downloaded
+#[test]
+fn job_lifecycle() {
+    let _u = AlteredToml::with_test_user(PathBuf::from("./config"));
+    let _o = AlteredToml::new(
+        "[user]\n",
+        "[user]\n\n[user.other]\nid = 1\nkey = \"other-key\"\nenabled = true\n",
+        PathType::Users,
+        PathBuf::from("./config"),
+    );
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+
+    let body = "{
+        \"word\": \"The University of Auckland\",
+        \"lang\": \"en\",
+        \"speed\": 1.0,
+        \"fmt\": \"mp3\"
+    }";
+
+    let response = client
+        .post(uri!("/api/jobs"))
+        .header(ContentType::new("application", "json"))
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+        .body(body)
+        .dispatch();
+    assert_eq!(response.status(), Status::Accepted);
+
+    let body: serde_json::Value =
+        serde_json::from_str(&response.into_string().unwrap()).expect("a valid json response");
+    let id = body["id"].as_str().expect("a job id").to_owned();
+
+    let report = wait_for_job(&client, &id);
+    assert_eq!(report["id"], id.as_str());
+    assert_eq!(report["status"], "done", "job failed: {}", report["error"]);
+
+    let response = client
+        .get(format!("/api/jobs/{}/audio", id))
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.headers().get_one("content-disposition").unwrap(),
+        "attachment; filename=\"output.mp3\""
+    );
+    assert_eq!(
+        response.headers().get_one("content-type").unwrap(),
+        "audio/mpeg"
+    );
+    assert!(!response.into_bytes().unwrap().is_empty());
+
+    //Jobs may only be viewed by the user who created them
+    let response = client
+        .get(format!("/api/jobs/{}", id))
+        .header(Header::new(API_KEY_HEADER, "other-key"))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+/// Test that invalid phrase packages are rejected before a job is created
+#[test]
+fn job_invalid_package() {
+    let _u = AlteredToml::with_test_user(PathBuf::from("./config"));
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+
+    let body = "{
+        \"word\": \"The University of Auckland\",
+        \"lang\": \"en\",
+        \"speed\": 1.0,
+        \"fmt\": \"this-will-never-exist\"
+    }";
+
+    let response = client
+        .post(uri!("/api/jobs"))
+        .header(ContentType::new("application", "json"))
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+        .body(body)
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+
+    let problem: serde_json::Value =
+        serde_json::from_str(&response.into_string().unwrap()).expect("a valid problem");
+    assert_eq!(problem["code"], "unsupported_format");
+    assert_eq!(
+        problem["detail"],
+        "Requested format (this-will-never-exist) is not supported by this api!"
+    );
+}
+
+/// Test that a job without a format is given one from the `Accept` header of the request
+#[test]
+fn job_negotiated() {
+    let _u = AlteredToml::with_test_user(PathBuf::from("./config"));
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+
+    let body = "{
+        \"word\": \"The University of Auckland\",
+        \"lang\": \"en\",
+        \"speed\": 1.0
+    }";
+
+    let response = client
+        .post(uri!("/api/jobs"))
+        .header(ContentType::new("application", "json"))
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+        .header(Header::new("Accept", "audio/mpeg"))
+        .body(body)
+        .dispatch();
+    assert_eq!(response.status(), Status::Accepted);
+    let body: serde_json::Value =
+        serde_json::from_str(&response.into_string().unwrap()).expect("a valid json response");
+    let id = body["id"].as_str().expect("a job id").to_owned();
+
+    let report = wait_for_job(&client, &id);
+    assert_eq!(report["status"], "done", "job failed: {}", report["error"]);
+    let response = client
+        .get(format!("/api/jobs/{}/audio", id))
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.headers().get_one("content-type").unwrap(),
+        "audio/mpeg"
+    );
+
+    //No job is created if none of the accepted media types may be produced
+    let response = client
+        .post(uri!("/api/jobs"))
+        .header(ContentType::new("application", "json"))
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+        .header(Header::new("Accept", "text/html"))
+        .body(
+            "{
+            \"word\": \"The University of Auckland\",
+            \"lang\": \"en\",
+            \"speed\": 1.0
+        }",
+        )
+        .dispatch();
+    assert_eq!(response.status(), Status::NotAcceptable);
+    let problem: serde_json::Value =
+        serde_json::from_str(&response.into_string().unwrap()).expect("a valid problem");
+    assert_eq!(problem["code"], "not_acceptable");
+}
+
+/// Test that unknown jobs are reported as missing
+#[test]
+fn job_not_found() {
+    let _u = AlteredToml::with_test_user(PathBuf::from("./config"));
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+
+    let response = client
+        .get(uri!("/api/jobs/not-a-real-job"))
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+    let problem: serde_json::Value =
+        serde_json::from_str(&response.into_string().unwrap()).expect("a valid problem");
+    assert_eq!(problem["code"], "job_not_found");
+
+    let response = client
+        .get(uri!("/api/jobs/not-a-real-job/audio"))
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+/// Test that the audio of a job which has since been removed from the cache is reported as expired
+#[test]
+fn job_audio_expired() {
+    let _u = AlteredToml::with_test_user(PathBuf::from("./config"));
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+
+    let phrase_package = PhrasePackage {
+        word: generate_random_alphanumeric(16),
+        lang: String::from("en"),
+        speed: 1.0,
+        fmt: Some(String::from("wav")),
+        blacklist_policy: None,
+        bleeps: vec![],
+    };
+    let response = client
+        .post(uri!("/api/jobs"))
+        .header(ContentType::new("application", "json"))
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+        .body(format!(
+            "{{\"word\": \"{}\", \"lang\": \"en\", \"speed\": 1.0, \"fmt\": \"wav\"}}",
+            phrase_package.word
+        ))
+        .dispatch();
+    assert_eq!(response.status(), Status::Accepted);
+    let body: serde_json::Value =
+        serde_json::from_str(&response.into_string().unwrap()).expect("a valid json response");
+    let id = body["id"].as_str().expect("a job id").to_owned();
+    assert_eq!(wait_for_job(&client, &id)["status"], "done");
+
+    std::fs::remove_file(PathBuf::from("./cache").join(format!(
+        "{}.wav",
+        phrase_package.filename_stem_properspeed()
+    )))
+    .unwrap();
+
+    let response = client
+        .get(format!("/api/jobs/{}/audio", id))
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+        .dispatch();
+    assert_eq!(response.status(), Status::Gone);
+    let problem: serde_json::Value =
+        serde_json::from_str(&response.into_string().unwrap()).expect("a valid problem");
+    assert_eq!(problem["code"], "job_audio_expired");
+}
@@ -0,0 +1,58 @@
+use config::API_KEY_HEADER;
+use festival_api::rocket;
+use rocket::http::{ContentType, Header, Status};
+use rocket::local::blocking::Client;
+use rocket::uri;
+use std::path::PathBuf;
+use utils::generate_random_alphanumeric;
+use utils::test_utils::{AlteredToml, TEST_API_KEY};
+
+/// Test that conversions are reflected in the exported metrics
+#[test]
+fn metrics_export() {
+    let _u = AlteredToml::with_test_user(PathBuf::from("./config"));
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+
+    //The second conversion of the same phrase is served from the cache
+    let word = generate_random_alphanumeric(12);
+    for _ in 0..2 {
+        let response = client
+            .post(uri!("/api/convert"))
+            .header(ContentType::new("application", "json"))
+            .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+            .body(format!(
+                "{{\"word\": \"{}\", \"lang\": \"en\", \"speed\": 1.0, \"fmt\": \"mp3\"}}",
+                word
+            ))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    //Invalid phrases are not labelled with the user's input
+    let response = client
+        .post(uri!("/api/convert"))
+        .header(ContentType::new("application", "json"))
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+        .body("{\"word\": \"hello\", \"lang\": \"not-a-lang\", \"speed\": 1.0, \"fmt\": \"mp3\"}")
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+
+    let response = client.get(uri!("/metrics")).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let body = response.into_string().unwrap();
+
+    let expected = [
+        "festival_requests_total{endpoint=\"convert\",fmt=\"mp3\",lang=\"en\",status=\"200\"} 2",
+        "festival_requests_total{endpoint=\"convert\",fmt=\"none\",lang=\"none\",status=\"400\"} 1",
+        "festival_cache_hits_total 1",
+        "festival_cache_misses_total 1",
+        "festival_flite_duration_seconds_count 1",
+        "festival_ffmpeg_duration_seconds_count 1",
+        "festival_cache_evictions_total 0",
+    ];
+    for line in expected {
+        assert!(body.contains(line), "missing `{}` in:\n{}", line, body);
+    }
+    assert!(body.contains("festival_cache_size_bytes "));
+    assert!(!body.contains("not-a-lang"));
+}
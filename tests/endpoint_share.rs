@@ -0,0 +1,136 @@
+use config::{PathType, API_KEY_HEADER};
+use festival_api::rocket;
+use festival_api::share::{ShareError, ShareLinks};
+use rocket::http::{ContentType, Header, Status};
+use rocket::local::blocking::Client;
+use rocket::uri;
+use std::path::PathBuf;
+use std::time::Duration;
+use utils::test_utils::{AlteredToml, TEST_API_KEY};
+
+/// Convert a phrase, returning the share link provided in place of the sound file
+fn share_link(client: &Client) -> serde_json::Value {
+    let response = client
+        .post("/api/convert?share=true")
+        .header(ContentType::new("application", "json"))
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+        .body(
+            "{
+            \"word\": \"The University of Auckland\",
+            \"lang\": \"en\",
+            \"speed\": 1.0,
+            \"fmt\": \"mp3\"
+        }",
+        )
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::JSON));
+    serde_json::from_str(&response.into_string().unwrap()).expect("a valid share link")
+}
+
+/// Test that links are only valid for the file and expiry they were signed with
+#[test]
+fn sign_and_verify() {
+    let links = ShareLinks::new("secret", Duration::from_secs(60));
+    let (url, expires) = links.sign("abc.mp3");
+    let signature = url.rsplit_once("signature=").unwrap().1;
+
+    links
+        .verify("abc.mp3", expires, signature)
+        .expect("a valid link");
+    assert_eq!(
+        links.verify("abd.mp3", expires, signature),
+        Err(ShareError::InvalidSignature)
+    );
+    assert_eq!(
+        links.verify("abc.mp3", expires + 1, signature),
+        Err(ShareError::InvalidSignature)
+    );
+    assert_eq!(
+        links.verify("abc.mp3", expires, "not-hex"),
+        Err(ShareError::InvalidSignature)
+    );
+
+    //Links signed with another secret are not valid
+    let other = ShareLinks::new("other-secret", Duration::from_secs(60));
+    assert_eq!(
+        other.verify("abc.mp3", expires, signature),
+        Err(ShareError::InvalidSignature)
+    );
+
+    let expired = ShareLinks::new("secret", Duration::from_secs(0));
+    let (url, expires) = expired.sign("abc.mp3");
+    let signature = url.rsplit_once("signature=").unwrap().1;
+    assert_eq!(
+        expired.verify("abc.mp3", expires, signature),
+        Err(ShareError::Expired)
+    );
+}
+
+/// Test that a share link may be downloaded without an api key, but not once altered
+#[test]
+fn shared_download() {
+    let _u = AlteredToml::with_test_user(PathBuf::from("./config"));
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+
+    let link = share_link(&client);
+    let url = link["url"].as_str().expect("a url");
+    assert!(url.starts_with("/api/share/"));
+    assert!(link["expires"].is_string());
+
+    let response = client.get(url).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.headers().get_one("content-type").unwrap(),
+        "audio/mpeg"
+    );
+    assert!(!response.into_bytes().unwrap().is_empty());
+
+    //Tampering with any part of the link invalidates it
+    let (path, query) = url.split_once('?').unwrap();
+    let tampered = [
+        format!("{}?{}", path.replace(".mp3", ".wav"), query),
+        format!("{}?{}", path, query.replace("expires=", "expires=9")),
+        format!("{}?{}", path, &query[..query.len() - 1]),
+        String::from(path),
+    ];
+    for url in tampered {
+        let response = client.get(&url).dispatch();
+        assert_eq!(response.status(), Status::Forbidden, "{}", url);
+        let problem: serde_json::Value =
+            serde_json::from_str(&response.into_string().unwrap()).expect("a valid problem");
+        assert_eq!(problem["code"], "invalid_signature");
+    }
+}
+
+/// Test that share links may not be used once they have expired
+#[test]
+fn expired_share_link() {
+    let _u = AlteredToml::with_test_user(PathBuf::from("./config"));
+    let _t = AlteredToml::new(
+        "SHARE_LINK_TTL_SECS = 86400",
+        "SHARE_LINK_TTL_SECS = 0",
+        PathType::General,
+        PathBuf::from("./config"),
+    );
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+
+    let link = share_link(&client);
+    let response = client.get(link["url"].as_str().expect("a url")).dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+    let problem: serde_json::Value =
+        serde_json::from_str(&response.into_string().unwrap()).expect("a valid problem");
+    assert_eq!(problem["code"], "link_expired");
+
+    //Share links are only created on request
+    let response = client
+        .post(uri!("/api/convert"))
+        .header(ContentType::new("application", "json"))
+        .header(Header::new(API_KEY_HEADER, TEST_API_KEY))
+        .body("{\"word\": \"hello\", \"lang\": \"en\", \"speed\": 1.0, \"fmt\": \"mp3\"}")
+        .dispatch();
+    assert_eq!(
+        response.headers().get_one("content-type").unwrap(),
+        "audio/mpeg"
+    );
+}